@@ -1,5 +1,6 @@
 use clap::Parser;
-use rmcp::handler::server::tool::{Parameters, ToolRouter};
+use rmcp::handler::server::tool::ToolRouter;
+use rmcp::handler::server::wrapper::Parameters;
 use rmcp::model::{ServerCapabilities, ServerInfo};
 use rmcp::transport::SseServer;
 use rmcp::transport::sse_server::SseServerConfig;
@@ -0,0 +1,440 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
+use langchain_rust::schemas::{AgentAction, FunctionCallResponse, LogTools};
+use rmcp::model::ToolAnnotations;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use uuid::Uuid;
+
+use crate::tool::extension::{ToolError, ToolExt};
+
+pub type ApprovalPredicate = Arc<dyn Fn(&str, Option<&ToolAnnotations>) -> bool + Send + Sync>;
+
+/// Which tool calls have to be approved before they run.
+#[derive(Clone, Default)]
+pub enum ApprovalPolicy {
+    /// Tools that may perform destructive updates. Following the MCP spec, a
+    /// tool is considered destructive unless it is annotated as read-only or
+    /// with `destructiveHint: false`.
+    #[default]
+    Destructive,
+    /// Every tool not annotated with `readOnlyHint`.
+    NotReadOnly,
+    Always,
+    /// Decides from the model-facing tool name and its annotations.
+    Custom(ApprovalPredicate),
+}
+
+impl ApprovalPolicy {
+    pub fn requires_approval(
+        &self,
+        tool_name: &str,
+        annotations: Option<&ToolAnnotations>,
+    ) -> bool {
+        let read_only = annotations.and_then(|a| a.read_only_hint).unwrap_or(false);
+        match self {
+            ApprovalPolicy::Destructive => {
+                !read_only && annotations.and_then(|a| a.destructive_hint).unwrap_or(true)
+            }
+            ApprovalPolicy::NotReadOnly => !read_only,
+            ApprovalPolicy::Always => true,
+            ApprovalPolicy::Custom(predicate) => predicate(tool_name, annotations),
+        }
+    }
+}
+
+impl Debug for ApprovalPolicy {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApprovalPolicy::Destructive => write!(f, "Destructive"),
+            ApprovalPolicy::NotReadOnly => write!(f, "NotReadOnly"),
+            ApprovalPolicy::Always => write!(f, "Always"),
+            ApprovalPolicy::Custom(_) => write!(f, "Custom"),
+        }
+    }
+}
+
+/// A tool call waiting for a decision.
+#[derive(Debug, Clone, Serialize)]
+pub struct ApprovalRequest {
+    /// Id to pass back to the [`ApprovalHandle`].
+    pub id: String,
+    pub tool_call_id: String,
+    pub tool_name: String,
+    pub arguments: Value,
+    pub annotations: Option<ToolAnnotations>,
+}
+
+#[derive(Debug, Clone)]
+pub enum ApprovalDecision {
+    Approve,
+    /// The tool is not called; the reason is given to the model instead.
+    Reject {
+        reason: String,
+    },
+    /// Runs the tool with these arguments instead of the model's.
+    Edit {
+        arguments: Value,
+    },
+}
+
+/// Answers approval requests raised by an executor.
+///
+/// Requests are delivered on the receiver returned by [`ApprovalHandle::new`]
+/// and, for streaming runs, as `approval_required` deltas on the stream.
+#[derive(Clone)]
+pub struct ApprovalHandle {
+    pending: Arc<Mutex<HashMap<String, oneshot::Sender<ApprovalDecision>>>>,
+    requests: UnboundedSender<ApprovalRequest>,
+}
+
+impl ApprovalHandle {
+    pub fn new() -> (Self, UnboundedReceiver<ApprovalRequest>) {
+        let (requests, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handle = Self {
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            requests,
+        };
+        (handle, rx)
+    }
+
+    /// Resolves the pending request `id`. Returns false if there is no such
+    /// request, e.g. because it was already answered.
+    pub fn respond(&self, id: &str, decision: ApprovalDecision) -> bool {
+        let sender = self.pending.lock().unwrap().remove(id);
+        match sender {
+            Some(sender) => sender.send(decision).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn approve(&self, id: &str) -> bool {
+        self.respond(id, ApprovalDecision::Approve)
+    }
+
+    pub fn reject(&self, id: &str, reason: impl ToString) -> bool {
+        self.respond(
+            id,
+            ApprovalDecision::Reject {
+                reason: reason.to_string(),
+            },
+        )
+    }
+
+    pub fn edit(&self, id: &str, arguments: Value) -> bool {
+        self.respond(id, ApprovalDecision::Edit { arguments })
+    }
+
+    /// Ids of the requests still waiting for a decision.
+    pub fn pending(&self) -> Vec<String> {
+        self.pending.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Makes `request` answerable and delivers it to the receiver. It can be
+    /// answered as soon as this returns, before the decision is awaited.
+    fn register(&self, request: &ApprovalRequest) -> PendingApproval<'_> {
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request.id.clone(), tx);
+        let _ = self.requests.send(request.clone());
+        PendingApproval {
+            rx,
+            _withdraw: WithdrawOnDrop {
+                pending: &self.pending,
+                id: request.id.clone(),
+            },
+        }
+    }
+}
+
+/// A registered request; it is withdrawn if dropped before it is decided.
+struct PendingApproval<'a> {
+    rx: oneshot::Receiver<ApprovalDecision>,
+    _withdraw: WithdrawOnDrop<'a>,
+}
+
+impl PendingApproval<'_> {
+    async fn decision(self) -> ApprovalDecision {
+        self.rx.await.unwrap_or_else(|_| ApprovalDecision::Reject {
+            reason: "the approval request was abandoned".to_string(),
+        })
+    }
+}
+
+struct WithdrawOnDrop<'a> {
+    pending: &'a Mutex<HashMap<String, oneshot::Sender<ApprovalDecision>>>,
+    id: String,
+}
+
+impl Drop for WithdrawOnDrop<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl Debug for ApprovalHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ApprovalHandle")
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+/// Writes the arguments of `actions`, the calls of one model turn, into the
+/// tool calls recorded in their logs, so that the chat history and memory
+/// show the arguments edited during approval rather than the model's.
+pub(crate) fn record_arguments(actions: &mut [&mut AgentAction]) {
+    let Some(first) = actions.first() else {
+        return;
+    };
+    let Ok(log) = serde_json::from_str::<LogTools>(&first.log) else {
+        return;
+    };
+    let Ok(mut calls) = serde_json::from_str::<Vec<FunctionCallResponse>>(&log.tools) else {
+        return;
+    };
+
+    let mut edited = false;
+    for action in actions.iter() {
+        let Ok(LogTools { tool_id, .. }) = serde_json::from_str(&action.log) else {
+            continue;
+        };
+        if let Some(call) = calls.iter_mut().find(|call| call.id == tool_id)
+            && call.function.arguments != action.tool_input
+        {
+            call.function.arguments = action.tool_input.clone();
+            edited = true;
+        }
+    }
+    if !edited {
+        return;
+    }
+
+    let Ok(tools) = serde_json::to_string(&calls) else {
+        return;
+    };
+    for action in actions.iter_mut() {
+        if let Ok(mut log) = serde_json::from_str::<LogTools>(&action.log) {
+            log.tools = tools.clone();
+            if let Ok(log) = serde_json::to_string(&log) {
+                action.log = log;
+            }
+        }
+    }
+}
+
+/// Holds tool calls back until they are approved.
+#[derive(Debug, Clone)]
+pub struct ApprovalGate {
+    pub policy: ApprovalPolicy,
+    pub handle: ApprovalHandle,
+}
+
+impl ApprovalGate {
+    pub fn new(policy: ApprovalPolicy, handle: ApprovalHandle) -> Self {
+        Self { policy, handle }
+    }
+
+    /// Waits for a decision on `action` if the policy requires one.
+    /// `on_request` is called once the request is raised and can be answered.
+    ///
+    /// Edited arguments are written back to `action.tool_input`; a rejection
+    /// is returned as [`ToolError::Rejected`].
+    pub(crate) async fn review(
+        &self,
+        tool: &dyn ToolExt,
+        tool_call_id: &str,
+        action: &mut AgentAction,
+        on_request: impl FnOnce(&ApprovalRequest),
+    ) -> Result<(), ToolError> {
+        let annotations = tool.annotations();
+        if !self
+            .policy
+            .requires_approval(&action.tool, annotations.as_ref())
+        {
+            return Ok(());
+        }
+
+        let request = ApprovalRequest {
+            id: Uuid::now_v7().to_string(),
+            tool_call_id: tool_call_id.to_string(),
+            tool_name: action.tool.clone(),
+            arguments: serde_json::from_str(&action.tool_input)
+                .unwrap_or_else(|_| Value::String(action.tool_input.clone())),
+            annotations,
+        };
+        // Registered before it is announced, so that an immediate answer is
+        // not lost.
+        let pending = self.handle.register(&request);
+        on_request(&request);
+
+        match pending.decision().await {
+            ApprovalDecision::Approve => Ok(()),
+            ApprovalDecision::Edit { arguments } => {
+                tracing::info!("Arguments of `{}` edited during approval", action.tool);
+                action.tool_input = arguments.to_string();
+                Ok(())
+            }
+            ApprovalDecision::Reject { reason } => Err(ToolError::Rejected(format!(
+                "The call to `{}` was rejected by the user: {reason}. Do not retry it unless asked to.",
+                action.tool
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use langchain_rust::schemas::FunctionDetail;
+    use serde_json::json;
+
+    use super::*;
+    use crate::tool::extension::ToolOutput;
+    use crate::tool::native::FnTool;
+
+    fn tool() -> FnTool {
+        FnTool::new(
+            "delete_file",
+            "Deletes a file",
+            json!({ "type": "object" }),
+            |_| async { Ok::<_, ToolError>(ToolOutput::from("deleted".to_string())) },
+        )
+    }
+
+    fn call(id: &str, arguments: &str) -> FunctionCallResponse {
+        FunctionCallResponse {
+            id: id.to_string(),
+            type_field: "function".to_string(),
+            function: FunctionDetail {
+                name: "delete_file".to_string(),
+                arguments: arguments.to_string(),
+            },
+        }
+    }
+
+    /// The actions of one turn calling `delete_file` with `arguments`.
+    fn turn(arguments: &[&str]) -> Vec<AgentAction> {
+        let calls = arguments
+            .iter()
+            .enumerate()
+            .map(|(i, args)| call(&format!("call_{i}"), args))
+            .collect::<Vec<_>>();
+        let tools = serde_json::to_string(&calls).unwrap();
+        calls
+            .iter()
+            .map(|call| AgentAction {
+                tool: "delete_file".to_string(),
+                tool_input: call.function.arguments.clone(),
+                log: serde_json::to_string(&LogTools {
+                    tool_id: call.id.clone(),
+                    tools: tools.clone(),
+                })
+                .unwrap(),
+            })
+            .collect()
+    }
+
+    /// Reviews a call, answering the request from `on_request` with `decide`.
+    async fn review(
+        decide: impl FnOnce(&ApprovalHandle, &str),
+    ) -> (Result<(), ToolError>, AgentAction) {
+        let (handle, _requests) = ApprovalHandle::new();
+        let gate = ApprovalGate::new(ApprovalPolicy::Always, handle.clone());
+        let mut action = turn(&[r#"{"path":"a.txt"}"#]).remove(0);
+
+        let result = gate
+            .review(&tool(), "call_0", &mut action, |request| {
+                decide(&handle, &request.id)
+            })
+            .await;
+        assert!(handle.pending().is_empty());
+        (result, action)
+    }
+
+    #[tokio::test]
+    async fn approved_calls_run_unchanged() {
+        let (result, action) = review(|handle, id| assert!(handle.approve(id))).await;
+        assert!(result.is_ok());
+        assert_eq!(action.tool_input, r#"{"path":"a.txt"}"#);
+    }
+
+    #[tokio::test]
+    async fn rejections_carry_the_reason() {
+        let (result, _) = review(|handle, id| assert!(handle.reject(id, "not this one"))).await;
+        match result {
+            Err(ToolError::Rejected(message)) => {
+                assert!(message.contains("not this one"), "{message}")
+            }
+            other => panic!("expected a rejection, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn edited_arguments_replace_the_model_ones() {
+        let (result, action) =
+            review(|handle, id| assert!(handle.edit(id, json!({ "path": "b.txt" })))).await;
+        assert!(result.is_ok());
+        assert_eq!(action.tool_input, r#"{"path":"b.txt"}"#);
+    }
+
+    #[tokio::test]
+    async fn calls_not_selected_by_the_policy_are_not_held() {
+        let (handle, mut requests) = ApprovalHandle::new();
+        let gate = ApprovalGate::new(ApprovalPolicy::NotReadOnly, handle);
+        let tool = tool().with_annotations(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        });
+        let mut action = turn(&["{}"]).remove(0);
+
+        let result = gate
+            .review(&tool, "call_0", &mut action, |_| {
+                panic!("no request expected")
+            })
+            .await;
+        assert!(result.is_ok());
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn dropped_reviews_withdraw_their_request() {
+        let (handle, mut requests) = ApprovalHandle::new();
+        let gate = ApprovalGate::new(ApprovalPolicy::Always, handle.clone());
+        let mut action = turn(&["{}"]).remove(0);
+        let tool = tool();
+
+        let review = gate.review(&tool, "call_0", &mut action, |_| {});
+        assert!(
+            tokio::time::timeout(Duration::from_millis(20), review)
+                .await
+                .is_err()
+        );
+
+        let request = requests.recv().await.unwrap();
+        assert!(handle.pending().is_empty());
+        assert!(!handle.approve(&request.id));
+    }
+
+    #[test]
+    fn records_edited_arguments_in_every_log_of_the_turn() {
+        let mut actions = turn(&[r#"{"path":"a.txt"}"#, r#"{"path":"c.txt"}"#]);
+        actions[0].tool_input = r#"{"path":"b.txt"}"#.to_string();
+
+        record_arguments(&mut actions.iter_mut().collect::<Vec<_>>());
+
+        for action in &actions {
+            let log = serde_json::from_str::<LogTools>(&action.log).unwrap();
+            let calls = serde_json::from_str::<Vec<FunctionCallResponse>>(&log.tools).unwrap();
+            let arguments = calls
+                .iter()
+                .map(|call| call.function.arguments.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(arguments, [r#"{"path":"b.txt"}"#, r#"{"path":"c.txt"}"#]);
+        }
+    }
+}
@@ -1,19 +1,33 @@
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use langchain_rust::agent::AgentError;
-use langchain_rust::chain::LLMChainBuilder;
 use langchain_rust::chain::options::ChainCallOptions;
-use langchain_rust::language_models::llm::LLM;
-use langchain_rust::language_models::options::CallOptions;
 use langchain_rust::llm::{OpenAI, OpenAIConfig};
-use langchain_rust::schemas::FunctionDefinition;
+use langchain_rust::schemas::Message;
 use langchain_rust::tools::Tool;
-use rmcp::RoleClient;
-use rmcp::model::InitializeRequestParam;
-use rmcp::service::RunningService;
+use regex::Regex;
+use serde_json::Value;
 
-use crate::agent::core::OpenAIMcpAgent;
-use crate::tool::rmcp::RmcpTool;
+use crate::agent::context::{ContextBudget, ContextManager, TokenCounter};
+use crate::agent::core::{ChainConfig, ChainOptions, OpenAIMcpAgent};
+use crate::agent::prompt::{McpPrompt, McpPromptLibrary};
+use crate::agent::scratchpad::{LlmSummarizer, Scratchpad, StepSummarizer};
+use crate::agent::toolset::{AgentToolset, McpToolSource};
+use crate::client::McpClient;
+use crate::client::config::McpServersConfig;
+use crate::client::connection::McpServerSpec;
+use crate::client::elicitation::ElicitationHandle;
+use crate::client::handler::AgentClientHandler;
+use crate::client::reconnect::ReconnectingClient;
+use crate::client::sampling::{Sampler, SamplingPolicy};
+use crate::tool::extension::{ToolError, ToolExt, ToolOutput};
+use crate::tool::filter::{ToolFilter, ToolMatcher};
+use crate::tool::native::{FnTool, LangchainTool};
+use crate::tool::registry::DEFAULT_NAMESPACE_SEPARATOR;
+use crate::tool::retry::RetryPolicy;
+use crate::tool::rmcp::RmcpToolOptions;
 
 const PREFIX: &str = r#"
 Assistant is designed to be able to assist with a wide range of tasks, from answering simple questions to providing in-depth explanations and discussions on a wide range of topics. As a language model, Assistant is able to generate human-like text based on the input it receives, allowing it to engage in natural-sounding conversations and provide responses that are coherent and relevant to the topic at hand.
@@ -24,10 +38,22 @@ Overall, Assistant is a powerful system that can help with a wide range of tasks
 "#;
 
 pub struct OpenAIMcpAgentBuilder {
-    tools: Option<Vec<Arc<dyn Tool>>>,
-    prefix: Option<String>,
-    options: Option<ChainCallOptions>,
+    tools: Option<Vec<Arc<dyn ToolExt>>>,
+    mcp_tools: Vec<McpToolSource>,
+    namespace_separator: String,
+    tool_filter: ToolFilter,
+    rmcp_options: RmcpToolOptions,
+    expose_resources: bool,
+    pinned_resources: Vec<String>,
+    prefix: Option<Vec<Message>>,
+    prompt_presets: Option<McpPromptLibrary>,
+    options: Option<ChainOptions>,
+    sampling: Option<(Sampler, SamplingPolicy)>,
+    elicitation: Option<ElicitationHandle>,
+    summarizer: Option<Arc<dyn StepSummarizer>>,
+    context_budget: Option<ContextBudget>,
 
+    model: String,
     llm: OpenAI<OpenAIConfig>,
 }
 
@@ -43,66 +69,404 @@ impl OpenAIMcpAgentBuilder {
 
         OpenAIMcpAgentBuilder {
             tools: None,
+            mcp_tools: Vec::new(),
+            namespace_separator: DEFAULT_NAMESPACE_SEPARATOR.to_string(),
+            tool_filter: ToolFilter::default(),
+            rmcp_options: RmcpToolOptions::default(),
+            expose_resources: false,
+            pinned_resources: Vec::new(),
             prefix: None,
+            prompt_presets: None,
             options: None,
+            sampling: None,
+            elicitation: None,
+            summarizer: None,
+            context_budget: None,
+            model: model.to_string(),
             llm,
         }
     }
 
-    pub fn mcp_tools(
+    pub fn mcp_tools<C: McpClient>(
         mut self,
-        mcp_client: Arc<RunningService<RoleClient, InitializeRequestParam>>,
+        mcp_client: Arc<C>,
         tools: Vec<rmcp::model::Tool>,
     ) -> Self {
-        let mut langchain_tools: Vec<Arc<dyn Tool>> = Vec::with_capacity(tools.len());
-        for tool in tools {
-            let t = RmcpTool::new(tool, mcp_client.clone());
-            langchain_tools.push(Arc::new(t));
+        // MCP tools are instantiated in `build`, so that options set after
+        // this call still apply to them.
+        self.mcp_tools
+            .push(McpToolSource::new(None, mcp_client, tools));
+        self
+    }
+
+    /// Like [`Self::mcp_tools`], but exposes each tool to the model as
+    /// `{namespace}{separator}{name}`, e.g. `github__search`, so that servers
+    /// with overlapping tool names can be used together. Characters not
+    /// allowed in function names are replaced with `_`.
+    pub fn namespaced_mcp_tools<C: McpClient>(
+        mut self,
+        namespace: impl ToString,
+        mcp_client: Arc<C>,
+        tools: Vec<rmcp::model::Tool>,
+    ) -> Self {
+        self.mcp_tools.push(McpToolSource::new(
+            Some(namespace.to_string()),
+            mcp_client,
+            tools,
+        ));
+        self
+    }
+
+    /// Connects to the MCP server described by `spec` and adds all its tools.
+    ///
+    /// The client reconnects on its own if the connection drops, see
+    /// [`ReconnectingClient`]. Its handler answers sampling and elicitation
+    /// requests if [`Self::sampling`] and [`Self::elicitation`] were called
+    /// before.
+    pub async fn mcp_server(self, spec: impl Into<McpServerSpec>) -> Result<Self, AgentError> {
+        let client = ReconnectingClient::connect(spec, self.client_handler()).await?;
+        let tools = client.tools();
+        Ok(self.mcp_tools(Arc::new(client), tools))
+    }
+
+    /// Like [`Self::mcp_server`], with the tools namespaced as in
+    /// [`Self::namespaced_mcp_tools`].
+    pub async fn namespaced_mcp_server(
+        self,
+        namespace: impl ToString,
+        spec: impl Into<McpServerSpec>,
+    ) -> Result<Self, AgentError> {
+        let client = ReconnectingClient::connect(spec, self.client_handler()).await?;
+        let tools = client.tools();
+        Ok(self.namespaced_mcp_tools(namespace, Arc::new(client), tools))
+    }
+
+    /// Starts every enabled server of an `mcpServers` config and adds its
+    /// tools, namespaced by server name.
+    pub async fn mcp_servers_config(
+        mut self,
+        config: &McpServersConfig,
+    ) -> Result<Self, AgentError> {
+        for (name, server) in config.enabled_servers() {
+            let spec = server
+                .to_spec()
+                .map_err(|e| AgentError::OtherError(format!("MCP server `{name}`: {e}")))?;
+            self = self
+                .namespaced_mcp_server(name, spec)
+                .await
+                .map_err(|e| AgentError::OtherError(format!("MCP server `{name}`: {e}")))?;
         }
+        Ok(self)
+    }
 
-        match self.tools {
-            Some(ref mut tools) => tools.extend(langchain_tools),
-            None => self.tools = Some(langchain_tools),
+    /// Like [`Self::mcp_servers_config`], reading the config from `path`.
+    pub async fn mcp_servers_config_file(
+        self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<Self, AgentError> {
+        let config = McpServersConfig::from_file(path)?;
+        self.mcp_servers_config(&config).await
+    }
+
+    fn client_handler(&self) -> AgentClientHandler {
+        let mut handler = AgentClientHandler::default();
+        if let Some((sampler, _)) = &self.sampling {
+            handler = handler.with_sampler(sampler.clone());
+        }
+        if let Some(elicitation) = &self.elicitation {
+            handler = handler.with_elicitation(elicitation.clone());
         }
+        handler
+    }
+
+    /// Adds a langchain tool next to the MCP tools. Native tools are not
+    /// namespaced but go through the same filters, and their names are made
+    /// valid function names the same way.
+    pub fn tool(self, tool: Arc<dyn Tool>) -> Self {
+        self.tool_ext(Arc::new(LangchainTool::new(tool)))
+    }
+
+    /// Adds a tool implementing [`ToolExt`], e.g. a [`FnTool`] or a
+    /// [`LangchainTool`] with annotations.
+    pub fn tool_ext(mut self, tool: Arc<dyn ToolExt>) -> Self {
+        self.tools.get_or_insert_with(Vec::new).push(tool);
+        self
+    }
 
+    /// Adds a tool backed by an async closure. `schema` is the JSON schema of
+    /// its arguments, which are validated before `func` is called.
+    pub fn fn_tool<F, Fut, T>(
+        self,
+        name: impl ToString,
+        description: impl ToString,
+        schema: Value,
+        func: F,
+    ) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, ToolError>> + Send + 'static,
+        T: Into<ToolOutput>,
+    {
+        self.tool_ext(Arc::new(FnTool::new(name, description, schema, func)))
+    }
+
+    /// Separator between namespace and tool name, `__` by default.
+    pub fn namespace_separator(mut self, separator: impl ToString) -> Self {
+        self.namespace_separator = separator.to_string();
+        self
+    }
+
+    /// Only exposes tools whose name matches one of the allowed glob
+    /// patterns (`*` and `?`), e.g. `github__*` or `get_*`. Names are matched
+    /// both with and without their namespace.
+    pub fn allow_tools(mut self, pattern: &str) -> Self {
+        self.tool_filter.allow.push(ToolMatcher::glob(pattern));
+        self
+    }
+
+    /// Hides tools whose name matches the glob pattern. Deny rules win
+    /// over allow rules.
+    pub fn deny_tools(mut self, pattern: &str) -> Self {
+        self.tool_filter.deny.push(ToolMatcher::glob(pattern));
+        self
+    }
+
+    pub fn allow_tools_matching(mut self, regex: Regex) -> Self {
+        self.tool_filter.allow.push(ToolMatcher::Regex(regex));
+        self
+    }
+
+    pub fn deny_tools_matching(mut self, regex: Regex) -> Self {
+        self.tool_filter.deny.push(ToolMatcher::Regex(regex));
+        self
+    }
+
+    /// Only exposes tools for which `predicate` returns true.
+    pub fn filter_tools<F>(mut self, predicate: F) -> Self
+    where
+        F: Fn(&rmcp::model::Tool) -> bool + Send + Sync + 'static,
+    {
+        self.tool_filter
+            .require
+            .push(ToolMatcher::Predicate(Arc::new(predicate)));
+        self
+    }
+
+    /// Only exposes tools annotated with `readOnlyHint`.
+    pub fn read_only_tools(mut self) -> Self {
+        self.tool_filter.require.push(ToolMatcher::ReadOnly);
+        self
+    }
+
+    /// Hides tools that may perform destructive updates, i.e. those not
+    /// annotated as read-only or with `destructiveHint: false`.
+    pub fn non_destructive_tools(mut self) -> Self {
+        self.tool_filter.require.push(ToolMatcher::NonDestructive);
+        self
+    }
+
+    /// Fixes common argument type mismatches (`7.0` for an integer, `"7"` for
+    /// a number, ...) using each MCP tool's input schema before the call is
+    /// dispatched. Disabled by default.
+    pub fn coerce_arguments(mut self, enabled: bool) -> Self {
+        self.rmcp_options.coerce_arguments = enabled;
+        self
+    }
+
+    /// Default deadline for MCP tool calls. When it expires the server is sent
+    /// `notifications/cancelled` and the model gets a timeout observation.
+    pub fn tool_timeout(mut self, timeout: Duration) -> Self {
+        self.rmcp_options.timeout = Some(timeout);
+        self
+    }
+
+    /// Overrides the default tool timeout for `tool_name`, either the
+    /// model-facing name or the MCP tool name.
+    pub fn tool_timeout_for(mut self, tool_name: impl ToString, timeout: Duration) -> Self {
+        self.rmcp_options
+            .tool_timeouts
+            .insert(tool_name.to_string(), timeout);
+        self
+    }
+
+    /// Retries MCP tool calls that fail with one of `policy.retry_on`. Tools
+    /// not annotated as idempotent or read-only are never retried.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.rmcp_options.retry = Some(policy);
+        self
+    }
+
+    /// Gives the model `list_resources` and `read_resource` tools for every
+    /// MCP server that publishes resources (namespaced like other tools).
+    /// Servers added without a namespace share one pair of tools. The tools
+    /// go through the same filters as the others.
+    pub fn mcp_resources(mut self, enabled: bool) -> Self {
+        self.expose_resources = enabled;
+        self
+    }
+
+    /// Reads the resource at `uri` at the start of every run and adds its
+    /// content to the system prompt.
+    pub fn pin_resource(mut self, uri: impl ToString) -> Self {
+        self.pinned_resources.push(uri.to_string());
         self
     }
 
     pub fn prefix<S: Into<String>>(mut self, prefix: S) -> Self {
-        self.prefix = Some(prefix.into());
+        self.prefix = Some(vec![Message::new_system_message(prefix.into())]);
+        self
+    }
+
+    /// Uses a prompt rendered by an MCP server (see [`McpPrompt::get`]) as the
+    /// prefix, instead of a static string. Its messages keep their roles.
+    pub fn mcp_prompt(mut self, prompt: McpPrompt) -> Self {
+        self.prefix = Some(prompt.to_messages());
+        self
+    }
+
+    /// Makes the server's prompts available as presets the prefix can be
+    /// switched to with [`OpenAIMcpAgent::use_prompt_preset`].
+    pub fn prompt_presets(mut self, presets: McpPromptLibrary) -> Self {
+        self.prompt_presets = Some(presets);
         self
     }
 
+    /// Lets MCP servers sample completions from the agent's model through
+    /// `sampler`, which must also be given to the client handler with
+    /// [`AgentClientHandler::with_sampler`].
+    ///
+    /// [`AgentClientHandler::with_sampler`]: crate::client::handler::AgentClientHandler::with_sampler
+    pub fn sampling(mut self, sampler: Sampler, policy: SamplingPolicy) -> Self {
+        self.sampling = Some((sampler, policy));
+        self
+    }
+
+    /// Lets MCP servers ask the user for input through `handle`. Servers
+    /// connected by the builder afterwards route `elicitation/create` to it,
+    /// and executors of the agent report its requests on their streams.
+    /// Clients connected elsewhere need
+    /// [`AgentClientHandler::with_elicitation`].
+    ///
+    /// [`AgentClientHandler::with_elicitation`]: crate::client::handler::AgentClientHandler::with_elicitation
+    pub fn elicitation(mut self, handle: ElicitationHandle) -> Self {
+        self.elicitation = Some(handle);
+        self
+    }
+
+    /// Options of the agent's chain. The streaming callback is kept across
+    /// rebuilds of the chain, e.g. when tools are relisted.
     pub fn options(mut self, options: ChainCallOptions) -> Self {
-        self.options = Some(options);
+        self.options = Some(options.into());
+        self
+    }
+
+    /// How many tokens prompts may use, see [`ContextBudget`]. Without a
+    /// budget, the chat history is sent whole, and runs of more than 10 steps
+    /// send their latest 5 after a summary of the others.
+    pub fn context_budget(mut self, budget: ContextBudget) -> Self {
+        self.context_budget = Some(budget);
+        self
+    }
+
+    /// Summarizes the steps left out of prompts with `summarizer`: those that
+    /// do not fit the context budget or, without one, all but the latest steps
+    /// of long runs (see [`Self::context_budget`]). Without a summarizer, or
+    /// when it fails, they are replaced by a digest listing each tool call
+    /// with the start of its result.
+    pub fn summarizer(mut self, summarizer: Arc<dyn StepSummarizer>) -> Self {
+        self.summarizer = Some(summarizer);
         self
     }
 
+    /// Summarizes the steps left out of prompts with `model`, through the same
+    /// API as the agent, see [`Self::summarizer`]. Use [`Self::summarizer`] with an
+    /// [`LlmSummarizer`] to change the prompt.
+    pub fn llm_summarizer(self, model: impl ToString) -> Self {
+        let summarizer = LlmSummarizer::new(self.llm.clone()).with_model(model);
+        self.summarizer(Arc::new(summarizer))
+    }
+
     pub fn build(self) -> Result<OpenAIMcpAgent, AgentError> {
-        let tools = self.tools.unwrap_or_default();
-        let prefix = self.prefix.unwrap_or_else(|| PREFIX.to_string());
-        let mut llm = self.llm;
+        let toolset = AgentToolset {
+            native_tools: self.tools.unwrap_or_default(),
+            mcp_sources: self.mcp_tools,
+            namespace_separator: self.namespace_separator,
+            tool_filter: self.tool_filter,
+            rmcp_options: self.rmcp_options,
+            expose_resources: self.expose_resources,
+            pinned_resources: self.pinned_resources,
+            rebuild_pending: false,
+        };
+
+        let context = ContextManager::new(
+            self.context_budget,
+            TokenCounter::for_model(&self.model)?,
+            Scratchpad::new(self.summarizer),
+        );
+
+        let sampler = self.sampling.map(|(sampler, policy)| {
+            sampler.attach(self.llm.clone(), self.model, policy);
+            sampler
+        });
 
-        let prompt = OpenAIMcpAgent::create_prompt(&prefix);
         let default_options = ChainCallOptions::default().with_max_tokens(1000);
+        let chain_config = ChainConfig {
+            llm: self.llm,
+            options: self.options.unwrap_or_else(|| default_options.into()),
+        };
+        let prefix = self
+            .prefix
+            .unwrap_or_else(|| vec![Message::new_system_message(PREFIX)]);
 
-        if !tools.is_empty() {
-            let functions = tools
-                .iter()
-                .map(FunctionDefinition::from_langchain_tool)
-                .collect::<Vec<FunctionDefinition>>();
+        OpenAIMcpAgent::new(
+            chain_config,
+            prefix,
+            toolset,
+            self.prompt_presets,
+            sampler,
+            self.elicitation,
+            context,
+        )
+    }
+}
 
-            llm.add_options(CallOptions::new().with_functions(functions));
-        }
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
 
-        let chain = Box::new(
-            LLMChainBuilder::new()
-                .prompt(prompt)
-                .llm(llm)
-                .options(self.options.unwrap_or(default_options))
-                .build()?,
-        );
+    use super::*;
+    use crate::agent::extension::AgentExt;
+    use crate::tool::extension::ToolCallContext;
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn connected_servers_send_elicitation_to_the_handle() {
+        use crate::client::testing::{StubListener, StubReply, StubServer, tool};
+
+        let server = StubServer::default().with_tool(tool("book"), StubReply::Elicit);
+        let listener = StubListener::start(server).await;
+        let (elicitation, mut requests) = ElicitationHandle::new();
+        let agent = OpenAIMcpAgentBuilder::new("key", "http://127.0.0.1:1", "gpt-4o")
+            .elicitation(elicitation.clone())
+            .mcp_server(listener.spec())
+            .await
+            .unwrap()
+            .build()
+            .unwrap();
+        assert!(agent.elicitation().is_some());
+
+        let answer = tokio::spawn(async move {
+            let request = requests.recv().await.unwrap();
+            assert_eq!(request.message, "Who is booking?");
+            elicitation.accept(&request.id, json!({ "name": "Ada" }))
+        });
+        let book = agent.tool_registry().get("book").unwrap().tool.clone();
+        let output = book
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap();
 
-        Ok(OpenAIMcpAgent { chain, tools })
+        assert!(answer.await.unwrap());
+        assert_eq!(output.content, r#"{"name":"Ada"}"#);
     }
 }
@@ -0,0 +1,554 @@
+use std::collections::HashSet;
+use std::ops::Range;
+use std::sync::Arc;
+
+use langchain_rust::agent::AgentError;
+use langchain_rust::prompt::PromptArgs;
+use langchain_rust::schemas::{LogTools, Message, MessageType};
+use serde_json::{Value, json};
+use tiktoken_rs::CoreBPE;
+
+use crate::agent::intermediate::IntermediateStep;
+use crate::agent::scratchpad::Scratchpad;
+use crate::tool::registry::ToolRegistry;
+
+/// Tokens the chat format adds around each message.
+const MESSAGE_OVERHEAD: usize = 4;
+/// Without a budget, runs with more steps than this get their older steps
+/// summarized.
+const SUMMARY_THRESHOLD: usize = 10;
+/// Latest steps still sent once the older ones are summarized.
+const MAX_STEPS: usize = 5;
+
+/// Counts tokens with the tokenizer of the target model.
+#[derive(Clone)]
+pub struct TokenCounter {
+    bpe: Arc<CoreBPE>,
+}
+
+impl TokenCounter {
+    /// The tokenizer of `model`, or `cl100k_base` for models tiktoken does
+    /// not know, such as most local ones.
+    pub fn for_model(model: &str) -> Result<Self, AgentError> {
+        let bpe = tiktoken_rs::get_bpe_from_model(model)
+            .or_else(|_| tiktoken_rs::cl100k_base())
+            .map_err(|e| AgentError::OtherError(format!("Failed to load tokenizer: {e}")))?;
+        Ok(Self { bpe: Arc::new(bpe) })
+    }
+
+    pub fn count(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+
+    pub fn count_message(&self, message: &Message) -> usize {
+        let tool_calls = message
+            .tool_calls
+            .as_ref()
+            .map_or(0, |calls| self.count(&calls.to_string()));
+        MESSAGE_OVERHEAD + self.count(&message.content) + tool_calls
+    }
+
+    pub fn count_messages(&self, messages: &[Message]) -> usize {
+        messages.iter().map(|m| self.count_message(m)).sum()
+    }
+
+    /// Cuts `text` to at most `max_tokens` tokens.
+    pub fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        let tokens = self.bpe.encode_with_special_tokens(text);
+        if tokens.len() <= max_tokens {
+            return text.to_string();
+        }
+        // A cut may split a multi-byte character, which does not decode.
+        (max_tokens.saturating_sub(4)..=max_tokens)
+            .rev()
+            .find_map(|len| self.bpe.decode(tokens[..len].to_vec()).ok())
+            .map(|text| format!("{text}..."))
+            .unwrap_or_default()
+    }
+}
+
+/// How many tokens a prompt may use, and what to keep when the steps of a
+/// run and the chat history do not fit.
+///
+/// The system prefix, input, pinned resources and tool definitions are
+/// always sent, and so is the latest turn of tool calls. When everything
+/// else does not fit, room is made for a summary of the steps left out, then
+/// the rest of the budget goes, in this order, to turns calling pinned tools,
+/// the `recent_steps` latest steps, the chat history (whole exchanges, latest
+/// first) and older steps.
+#[derive(Debug, Clone)]
+pub struct ContextBudget {
+    /// Context window of the model.
+    pub max_tokens: usize,
+    /// Tokens left free for the completion. Should be at least the
+    /// `max_tokens` of the chain options, 1000 by default.
+    pub reserved_output_tokens: usize,
+    pub recent_steps: usize,
+    /// Upper bound of the summary of the steps left out.
+    pub summary_tokens: usize,
+    /// Tools whose results are kept verbatim while they fit.
+    pub pinned_tools: HashSet<String>,
+}
+
+impl ContextBudget {
+    pub fn new(max_tokens: usize) -> Self {
+        Self {
+            max_tokens,
+            reserved_output_tokens: 1000,
+            recent_steps: 5,
+            summary_tokens: 1000,
+            pinned_tools: HashSet::new(),
+        }
+    }
+
+    pub fn with_reserved_output_tokens(mut self, tokens: usize) -> Self {
+        self.reserved_output_tokens = tokens;
+        self
+    }
+
+    pub fn with_recent_steps(mut self, steps: usize) -> Self {
+        self.recent_steps = steps;
+        self
+    }
+
+    pub fn with_summary_tokens(mut self, tokens: usize) -> Self {
+        self.summary_tokens = tokens;
+        self
+    }
+
+    /// Keeps the results of `tool`, by the name the model sees, out of
+    /// summaries while they fit.
+    pub fn pin_tool(mut self, tool: impl ToString) -> Self {
+        self.pinned_tools.insert(tool.to_string());
+        self
+    }
+}
+
+/// Fits the steps of a run and the chat history into a [`ContextBudget`].
+/// Without a budget, only the number of steps sent is limited.
+pub(crate) struct ContextManager {
+    budget: Option<ContextBudget>,
+    counter: TokenCounter,
+    scratchpad: Scratchpad,
+}
+
+impl ContextManager {
+    pub fn new(
+        budget: Option<ContextBudget>,
+        counter: TokenCounter,
+        scratchpad: Scratchpad,
+    ) -> Self {
+        Self {
+            budget,
+            counter,
+            scratchpad,
+        }
+    }
+
+    /// Tokens taken by the function definitions of `registry`, 0 without a
+    /// budget since nothing is trimmed then.
+    pub fn count_tools(&self, registry: &ToolRegistry) -> usize {
+        if self.budget.is_none() {
+            return 0;
+        }
+        registry
+            .iter()
+            .map(|entry| {
+                let tool = &entry.tool;
+                self.counter.count(&entry.name)
+                    + self.counter.count(&tool.description())
+                    + self.counter.count(&tool.parameters().to_string())
+                    + MESSAGE_OVERHEAD
+            })
+            .sum()
+    }
+
+    /// Builds the scratchpad of `steps` and trims the `chat_history` of
+    /// `inputs`, so that the prompt fits the budget.
+    pub async fn fit(
+        &self,
+        prefix: &[Message],
+        tools_tokens: usize,
+        steps: &[impl IntermediateStep],
+        inputs: &mut PromptArgs,
+    ) -> Result<Vec<Message>, AgentError> {
+        let Some(budget) = &self.budget else {
+            return self.fit_steps(steps).await;
+        };
+
+        let history: Vec<Message> = match inputs.get("chat_history") {
+            Some(history) => serde_json::from_value(history.clone())?,
+            None => Vec::new(),
+        };
+        let pinned_resources: Vec<Message> = match inputs.get("pinned_resources") {
+            Some(resources) => serde_json::from_value(resources.clone())?,
+            None => Vec::new(),
+        };
+        let input = match inputs.get("input") {
+            Some(Value::String(input)) => self.counter.count(input),
+            Some(input) => self.counter.count(&input.to_string()),
+            None => 0,
+        };
+
+        let fixed = self.counter.count_messages(prefix)
+            + input
+            + MESSAGE_OVERHEAD
+            + self.counter.count_messages(&pinned_resources)
+            + tools_tokens;
+        let available = budget
+            .max_tokens
+            .saturating_sub(budget.reserved_output_tokens + fixed);
+        if available == 0 {
+            tracing::warn!("Context budget exceeded by the prompt alone ({fixed} tokens)");
+        }
+
+        let turns = turns(steps);
+        let turn_messages = turns
+            .iter()
+            .map(|turn| {
+                let mut messages = Vec::new();
+                for step in &steps[turn.clone()] {
+                    step.append_to_conversation(&mut messages)?;
+                }
+                Ok(messages)
+            })
+            .collect::<Result<Vec<_>, AgentError>>()?;
+        let turn_tokens = turn_messages
+            .iter()
+            .map(|messages| self.counter.count_messages(messages))
+            .collect::<Vec<_>>();
+
+        let history_tokens = self.counter.count_messages(&history);
+        if turn_tokens.iter().sum::<usize>() + history_tokens <= available {
+            return Ok(turn_messages.into_iter().flatten().collect());
+        }
+
+        let mut remaining = available.saturating_sub(budget.summary_tokens);
+        let mut keep = vec![false; turns.len()];
+
+        // The model needs the results of the calls it just made.
+        if let Some(last) = turns.len().checked_sub(1) {
+            remaining = remaining.saturating_sub(turn_tokens[last]);
+            keep[last] = true;
+        }
+        for i in (0..turns.len()).rev() {
+            let pinned = steps[turns[i].clone()]
+                .iter()
+                .any(|step| budget.pinned_tools.contains(&step.action().tool));
+            if pinned && !keep[i] {
+                take(turn_tokens[i], &mut keep[i], &mut remaining);
+            }
+        }
+        let mut recent = 0;
+        for i in (0..turns.len()).rev() {
+            if recent >= budget.recent_steps {
+                break;
+            }
+            recent += turns[i].len();
+            if !keep[i] && !take(turn_tokens[i], &mut keep[i], &mut remaining) {
+                break;
+            }
+        }
+        let history = self.trim_history(history, &mut remaining);
+        for i in (0..turns.len()).rev() {
+            if !keep[i] && !take(turn_tokens[i], &mut keep[i], &mut remaining) {
+                break;
+            }
+        }
+        inputs.insert("chat_history".to_string(), json!(history));
+
+        let left_out = turns
+            .iter()
+            .zip(&keep)
+            .filter(|(_, keep)| !**keep)
+            .flat_map(|(turn, _)| &steps[turn.clone()])
+            .collect::<Vec<_>>();
+        let mut thoughts = Vec::new();
+        if !left_out.is_empty() {
+            let summary = self.scratchpad.summarize(&left_out).await;
+            let summary = self.counter.truncate(&summary, budget.summary_tokens);
+            thoughts.push(Message::new_system_message(format!(
+                "Previous {} steps summary:\n{summary}",
+                left_out.len()
+            )));
+        }
+        for (messages, _) in turn_messages
+            .into_iter()
+            .zip(&keep)
+            .filter(|(_, keep)| **keep)
+        {
+            thoughts.extend(messages);
+        }
+
+        Ok(thoughts)
+    }
+
+    /// Builds the scratchpad of `steps` without a budget. Once a run has more
+    /// than [`SUMMARY_THRESHOLD`] steps, the turns within its latest
+    /// [`MAX_STEPS`] steps are sent, and always the latest turn, after a
+    /// summary of the others.
+    async fn fit_steps(&self, steps: &[impl IntermediateStep]) -> Result<Vec<Message>, AgentError> {
+        let mut start = 0;
+        if steps.len() > SUMMARY_THRESHOLD {
+            let turns = turns(steps);
+            start = turns.last().map_or(0, |turn| turn.start);
+            for turn in turns.iter().rev().skip(1) {
+                if steps.len() - turn.start > MAX_STEPS {
+                    break;
+                }
+                start = turn.start;
+            }
+        }
+
+        let mut thoughts = Vec::new();
+        if start > 0 {
+            let left_out = steps[..start].iter().collect::<Vec<_>>();
+            let summary = self.scratchpad.summarize(&left_out).await;
+            thoughts.push(Message::new_system_message(format!(
+                "Previous {start} steps summary:\n{summary}"
+            )));
+        }
+        for step in &steps[start..] {
+            step.append_to_conversation(&mut thoughts)?;
+        }
+        Ok(thoughts)
+    }
+
+    /// Keeps the latest exchanges of `history` that fit in `remaining`,
+    /// starting at a user message so that no tool result loses its call.
+    fn trim_history(&self, mut history: Vec<Message>, remaining: &mut usize) -> Vec<Message> {
+        let mut used = 0;
+        let mut start = history.len();
+        let mut kept = 0;
+        for (i, message) in history.iter().enumerate().rev() {
+            used += self.counter.count_message(message);
+            if used > *remaining {
+                break;
+            }
+            if matches!(message.message_type, MessageType::HumanMessage) {
+                start = i;
+                kept = used;
+            }
+        }
+        *remaining -= kept;
+        if start > 0 {
+            tracing::debug!("Dropped {start} chat history messages to fit the context budget");
+        }
+        history.split_off(start)
+    }
+}
+
+/// Keeps a turn of `tokens` tokens if it fits in `remaining`.
+fn take(tokens: usize, keep: &mut bool, remaining: &mut usize) -> bool {
+    if tokens <= *remaining {
+        *remaining -= tokens;
+        *keep = true;
+    }
+    *keep
+}
+
+/// Groups steps by the model turn that requested them, since a turn's tool
+/// call message must be followed by all of its results.
+fn turns(steps: &[impl IntermediateStep]) -> Vec<Range<usize>> {
+    let mut turns: Vec<Range<usize>> = Vec::new();
+    let mut previous: Option<String> = None;
+    for (i, step) in steps.iter().enumerate() {
+        let calls = serde_json::from_str::<LogTools>(&step.action().log)
+            .ok()
+            .map(|log| log.tools);
+        match turns.last_mut() {
+            Some(turn) if calls.is_some() && calls == previous => turn.end = i + 1,
+            _ => turns.push(i..i + 1),
+        }
+        previous = calls;
+    }
+    turns
+}
+
+#[cfg(test)]
+mod tests {
+    use async_trait::async_trait;
+    use langchain_rust::schemas::AgentAction;
+
+    use super::*;
+    use crate::agent::scratchpad::{StepRecord, StepSummarizer};
+
+    struct CountingSummarizer;
+
+    #[async_trait]
+    impl StepSummarizer for CountingSummarizer {
+        async fn summarize(
+            &self,
+            _previous: Option<&str>,
+            steps: &[StepRecord],
+        ) -> Result<String, AgentError> {
+            Ok(format!("{} steps done", steps.len()))
+        }
+    }
+
+    fn counter() -> TokenCounter {
+        TokenCounter::for_model("gpt-4o").unwrap()
+    }
+
+    /// A step of the turn that called `calls`, answering the call `tool_id`.
+    fn step(tool_id: &str, calls: &[&str], observation: &str) -> (AgentAction, String) {
+        let tools = calls
+            .iter()
+            .map(|id| {
+                json!({
+                    "id": id,
+                    "type": "function",
+                    "function": { "name": "search", "arguments": "{}" }
+                })
+            })
+            .collect::<Vec<_>>();
+        let log = json!({ "tool_id": tool_id, "tools": json!(tools).to_string() });
+        let action = AgentAction {
+            tool: "search".to_string(),
+            tool_input: "{}".to_string(),
+            log: log.to_string(),
+        };
+        (action, observation.to_string())
+    }
+
+    #[test]
+    fn turns_group_parallel_calls() {
+        let steps = vec![
+            step("a", &["a", "b"], "1"),
+            step("b", &["a", "b"], "2"),
+            step("c", &["c"], "3"),
+            step("d", &["d"], "4"),
+        ];
+        assert_eq!(turns(&steps), vec![0..2, 2..3, 3..4]);
+    }
+
+    #[test]
+    fn trim_history_keeps_whole_exchanges() {
+        let manager = ContextManager::new(None, counter(), Scratchpad::new(None));
+        let history = vec![
+            Message::new_human_message("first question"),
+            Message::new_ai_message("first answer"),
+            Message::new_human_message("second question"),
+            Message::new_ai_message("second answer"),
+        ];
+        let last_exchange = manager.counter.count_messages(&history[2..]);
+
+        // One token short of the last two exchanges keeps only the last one.
+        let mut remaining = manager.counter.count_messages(&history) - 1;
+        let trimmed = manager.trim_history(history.clone(), &mut remaining);
+        assert_eq!(trimmed.len(), 2);
+        assert_eq!(trimmed[0].content, "second question");
+        assert_eq!(
+            remaining,
+            manager.counter.count_messages(&history) - 1 - last_exchange
+        );
+
+        // An answer alone is never kept without its question.
+        let mut remaining = last_exchange - 1;
+        assert!(manager.trim_history(history, &mut remaining).is_empty());
+        assert_eq!(remaining, last_exchange - 1);
+    }
+
+    #[tokio::test]
+    async fn fit_without_budget_sends_short_runs_whole() {
+        let manager = ContextManager::new(None, counter(), Scratchpad::new(None));
+        let steps = vec![step("a", &["a"], "1"), step("b", &["b"], "2")];
+        let mut inputs = PromptArgs::new();
+
+        let thoughts = manager.fit(&[], 0, &steps, &mut inputs).await.unwrap();
+
+        // One tool call message and one result per turn.
+        assert_eq!(thoughts.len(), 4);
+        assert!(!inputs.contains_key("chat_history"));
+    }
+
+    #[tokio::test]
+    async fn fit_without_budget_limits_long_runs() {
+        let manager = ContextManager::new(None, counter(), Scratchpad::new(None));
+        let ids = (0..12).map(|i| format!("s{i}")).collect::<Vec<_>>();
+        // Eight single calls, then a turn of four parallel calls.
+        let parallel = ids[8..].iter().map(String::as_str).collect::<Vec<_>>();
+        let steps = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| match i {
+                0..8 => step(id, &[id], "ok"),
+                _ => step(id, &parallel, "ok"),
+            })
+            .collect::<Vec<_>>();
+        let mut inputs = PromptArgs::new();
+
+        let thoughts = manager.fit(&[], 0, &steps, &mut inputs).await.unwrap();
+
+        // The latest 5 steps hold the parallel turn and one single call.
+        assert!(thoughts[0].content.starts_with("Previous 7 steps summary:"));
+        assert_eq!(thoughts[2].id.as_deref(), Some("s7"));
+        let results = thoughts.iter().filter(|m| m.id.is_some()).count();
+        assert_eq!(results, 5);
+    }
+
+    #[tokio::test]
+    async fn fit_without_budget_uses_the_summarizer() {
+        let scratchpad = Scratchpad::new(Some(Arc::new(CountingSummarizer)));
+        let manager = ContextManager::new(None, counter(), scratchpad);
+        let ids = (0..11).map(|i| format!("s{i}")).collect::<Vec<_>>();
+        let steps = ids
+            .iter()
+            .map(|id| step(id, &[id], "ok"))
+            .collect::<Vec<_>>();
+        let mut inputs = PromptArgs::new();
+
+        let thoughts = manager.fit(&[], 0, &steps, &mut inputs).await.unwrap();
+
+        assert_eq!(
+            thoughts[0].content,
+            "Previous 6 steps summary:\n6 steps done"
+        );
+    }
+
+    #[tokio::test]
+    async fn fit_summarizes_older_turns() {
+        let budget = ContextBudget::new(400)
+            .with_reserved_output_tokens(0)
+            .with_summary_tokens(50)
+            .with_recent_steps(1);
+        let manager = ContextManager::new(Some(budget), counter(), Scratchpad::new(None));
+        let observation = "result ".repeat(200);
+        let steps = vec![
+            step("a", &["a"], &observation),
+            step("b", &["b"], &observation),
+            step("c", &["c"], &observation),
+        ];
+        let mut inputs = PromptArgs::new();
+
+        let thoughts = manager.fit(&[], 0, &steps, &mut inputs).await.unwrap();
+
+        assert_eq!(thoughts.len(), 3);
+        assert!(thoughts[0].content.starts_with("Previous 2 steps summary:"));
+        assert_eq!(thoughts[2].id.as_deref(), Some("c"));
+        assert_eq!(inputs["chat_history"], json!([]));
+    }
+
+    #[tokio::test]
+    async fn fit_never_splits_parallel_calls() {
+        // Room for the latest turn and one more step, but not two.
+        let budget = ContextBudget::new(600)
+            .with_reserved_output_tokens(0)
+            .with_summary_tokens(50)
+            .with_recent_steps(3);
+        let manager = ContextManager::new(Some(budget), counter(), Scratchpad::new(None));
+        let observation = "result ".repeat(200);
+        let steps = vec![
+            step("a", &["a", "b"], &observation),
+            step("b", &["a", "b"], &observation),
+            step("c", &["c"], &observation),
+        ];
+        let mut inputs = PromptArgs::new();
+
+        let thoughts = manager.fit(&[], 0, &steps, &mut inputs).await.unwrap();
+
+        assert!(thoughts[0].content.starts_with("Previous 2 steps summary:"));
+        assert!(thoughts.iter().all(|m| m.id.as_deref() != Some("a")));
+        assert!(thoughts.iter().all(|m| m.id.as_deref() != Some("b")));
+    }
+}
@@ -1,77 +1,279 @@
-use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, RwLock};
 
 use async_trait::async_trait;
 use langchain_rust::agent::{Agent, AgentError};
-use langchain_rust::chain::Chain;
+use langchain_rust::chain::options::ChainCallOptions;
+use langchain_rust::chain::{Chain, LLMChainBuilder};
+use langchain_rust::language_models::llm::LLM;
+use langchain_rust::language_models::options::CallOptions;
+use langchain_rust::llm::{OpenAI, OpenAIConfig};
 use langchain_rust::prompt::{HumanMessagePromptTemplate, MessageFormatterStruct, PromptArgs};
 use langchain_rust::schemas::{
-    AgentAction, AgentEvent, AgentFinish, FunctionCallResponse, LogTools, Message,
+    AgentAction, AgentEvent, AgentFinish, FunctionCallResponse, FunctionDefinition, LogTools,
+    Message,
 };
+use langchain_rust::template_jinja2;
 use langchain_rust::tools::Tool;
-use langchain_rust::{
-    fmt_message, fmt_placeholder, fmt_template, message_formatter, template_jinja2,
-};
 use serde_json::json;
 
+use crate::agent::context::ContextManager;
 use crate::agent::extension::{AgentEventChunk, AgentExt, AgentStream, DeltaEvent};
 use crate::agent::intermediate::IntermediateStep;
+use crate::agent::prompt::McpPromptLibrary;
+use crate::agent::toolset::AgentToolset;
+use crate::client::elicitation::ElicitationHandle;
+use crate::client::sampling::Sampler;
+use crate::tool::filter::DroppedTool;
+use crate::tool::registry::ToolRegistry;
+
+/// What the agent's chain is built from. The chain is rebuilt whenever the
+/// tools or the prefix change, since function definitions are part of the LLM
+/// options.
+pub(crate) struct ChainConfig {
+    pub llm: OpenAI<OpenAIConfig>,
+    pub options: ChainOptions,
+}
+
+/// [`ChainCallOptions`] kept for every rebuild of the chain. They are
+/// neither `Clone` nor `Sync` because of the streaming callback, so the
+/// callback is shared and each chain gets a closure calling it.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ChainOptions {
+    max_tokens: Option<u32>,
+    temperature: Option<f32>,
+    stop_words: Option<Vec<String>>,
+    top_k: Option<usize>,
+    top_p: Option<f32>,
+    seed: Option<usize>,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+    repetition_penalty: Option<f32>,
+    streaming_func: Option<SharedStreamingFunc>,
+}
+
+type StreamingFunc =
+    dyn FnMut(String) -> Pin<Box<dyn Future<Output = Result<(), ()>> + Send>> + Send;
+
+#[derive(Clone)]
+struct SharedStreamingFunc(Arc<Mutex<Box<StreamingFunc>>>);
+
+impl SharedStreamingFunc {
+    fn to_streaming_func(&self) -> Box<StreamingFunc> {
+        let func = self.0.clone();
+        Box::new(move |chunk| (func.lock().unwrap())(chunk))
+    }
+}
+
+impl Debug for SharedStreamingFunc {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("SharedStreamingFunc")
+    }
+}
+
+impl From<ChainCallOptions> for ChainOptions {
+    fn from(options: ChainCallOptions) -> Self {
+        Self {
+            max_tokens: options.max_tokens,
+            temperature: options.temperature,
+            stop_words: options.stop_words,
+            top_k: options.top_k,
+            top_p: options.top_p,
+            seed: options.seed,
+            min_length: options.min_length,
+            max_length: options.max_length,
+            repetition_penalty: options.repetition_penalty,
+            streaming_func: options
+                .streaming_func
+                .map(|func| SharedStreamingFunc(Arc::new(Mutex::new(func)))),
+        }
+    }
+}
+
+impl ChainOptions {
+    fn to_call_options(&self) -> ChainCallOptions {
+        let mut options = ChainCallOptions::new();
+        options.max_tokens = self.max_tokens;
+        options.temperature = self.temperature;
+        options.stop_words = self.stop_words.clone();
+        options.top_k = self.top_k;
+        options.top_p = self.top_p;
+        options.seed = self.seed;
+        options.min_length = self.min_length;
+        options.max_length = self.max_length;
+        options.repetition_penalty = self.repetition_penalty;
+        options.streaming_func = self
+            .streaming_func
+            .as_ref()
+            .map(SharedStreamingFunc::to_streaming_func);
+        options
+    }
+}
+
+impl ChainConfig {
+    fn build_chain(
+        &self,
+        prefix: &[Message],
+        registry: &ToolRegistry,
+    ) -> Result<Arc<dyn Chain>, AgentError> {
+        let mut llm = self.llm.clone();
+        if !registry.is_empty() {
+            let functions = registry
+                .iter()
+                .map(|entry| FunctionDefinition {
+                    name: entry.name.clone(),
+                    description: entry.tool.description(),
+                    parameters: entry.tool.parameters(),
+                })
+                .collect::<Vec<FunctionDefinition>>();
+
+            llm.add_options(CallOptions::new().with_functions(functions));
+        }
+
+        let chain = LLMChainBuilder::new()
+            .prompt(OpenAIMcpAgent::create_prompt(prefix))
+            .llm(llm)
+            .options(self.options.to_call_options())
+            .build()?;
+
+        Ok(Arc::new(chain))
+    }
+}
+
+struct AgentState {
+    chain: Arc<dyn Chain>,
+    prefix: Vec<Message>,
+    registry: ToolRegistry,
+}
 
 pub struct OpenAIMcpAgent {
-    pub chain: Box<dyn Chain>,
-    pub tools: Vec<Arc<dyn Tool>>,
+    state: RwLock<AgentState>,
+    chain_config: ChainConfig,
+    toolset: tokio::sync::Mutex<AgentToolset>,
+    prompt_presets: Option<McpPromptLibrary>,
+    sampler: Option<Sampler>,
+    elicitation: Option<ElicitationHandle>,
+    context: ContextManager,
 }
 
 impl OpenAIMcpAgent {
-    pub(crate) fn create_prompt(prefix: &str) -> MessageFormatterStruct {
-        let message = Message::new_system_message(prefix);
-        let template = HumanMessagePromptTemplate::new(template_jinja2!("{{input}}", "input"));
+    pub(crate) fn new(
+        chain_config: ChainConfig,
+        prefix: Vec<Message>,
+        toolset: AgentToolset,
+        prompt_presets: Option<McpPromptLibrary>,
+        sampler: Option<Sampler>,
+        elicitation: Option<ElicitationHandle>,
+        context: ContextManager,
+    ) -> Result<Self, AgentError> {
+        let registry = toolset.build_registry()?;
+        let chain = chain_config.build_chain(&prefix, &registry)?;
+
+        Ok(Self {
+            state: RwLock::new(AgentState {
+                chain,
+                prefix,
+                registry,
+            }),
+            chain_config,
+            toolset: tokio::sync::Mutex::new(toolset),
+            prompt_presets,
+            sampler,
+            elicitation,
+            context,
+        })
+    }
 
-        message_formatter![
-            fmt_message!(message),
-            fmt_template!(template),
-            fmt_placeholder!("chat_history"),
-            fmt_placeholder!("agent_scratchpad")
-        ]
+    /// The MCP prompts the agent's prefix can be switched to.
+    pub fn prompt_presets(&self) -> Option<&McpPromptLibrary> {
+        self.prompt_presets.as_ref()
     }
 
-    pub fn construct_scratchpad(
+    /// Replaces the agent's prefix with the preset `name` rendered with
+    /// `arguments`. Applies to runs planned after the call.
+    pub async fn use_prompt_preset(
         &self,
-        intermediate_steps: &[impl IntermediateStep],
-    ) -> Result<Vec<Message>, AgentError> {
-        let mut thoughts: Vec<Message> = vec![];
+        name: &str,
+        arguments: Option<rmcp::model::JsonObject>,
+    ) -> Result<(), AgentError> {
+        let Some(presets) = &self.prompt_presets else {
+            return Err(AgentError::OtherError(
+                "No prompt presets configured".to_string(),
+            ));
+        };
+        let prefix = presets.get(name, arguments).await?.to_messages();
+
+        let mut state = self.state.write().unwrap();
+        state.chain = self.chain_config.build_chain(&prefix, &state.registry)?;
+        state.prefix = prefix;
+        Ok(())
+    }
 
-        const MAX_STEPS: usize = 5;
-        const SUMMARY_THRESHOLD: usize = 10;
+    pub fn chain(&self) -> Arc<dyn Chain> {
+        self.state.read().unwrap().chain.clone()
+    }
 
-        if intermediate_steps.len() > SUMMARY_THRESHOLD {
-            let summary_msg = self.create_summary_message(
-                &intermediate_steps[..intermediate_steps.len() - MAX_STEPS],
-            )?;
-            thoughts.push(summary_msg);
+    /// MCP tools that were left out by the builder's tool filters.
+    pub fn dropped_tools(&self) -> Vec<DroppedTool> {
+        self.state.read().unwrap().registry.dropped().to_vec()
+    }
 
-            for step in &intermediate_steps[intermediate_steps.len() - MAX_STEPS..] {
-                step.append_to_conversation(&mut thoughts)?;
-            }
-        } else {
-            for step in intermediate_steps {
-                step.append_to_conversation(&mut thoughts)?;
-            }
+    pub(crate) fn create_prompt(prefix: &[Message]) -> MessageFormatterStruct {
+        let template = HumanMessagePromptTemplate::new(template_jinja2!("{{input}}", "input"));
+
+        let mut prompt = MessageFormatterStruct::new();
+        for message in prefix {
+            prompt.add_message(message.clone());
         }
+        prompt.add_messages_placeholder("pinned_resources");
+        prompt.add_template(Box::new(template));
+        prompt.add_messages_placeholder("chat_history");
+        prompt.add_messages_placeholder("agent_scratchpad");
+        prompt
+    }
 
-        Ok(thoughts)
+    /// The messages of the steps taken so far, with the steps that do not
+    /// fit the context budget replaced by a summary.
+    pub async fn construct_scratchpad(
+        &self,
+        intermediate_steps: &[impl IntermediateStep],
+    ) -> Result<Vec<Message>, AgentError> {
+        self.fit_to_context(intermediate_steps, &mut PromptArgs::new())
+            .await
     }
 
-    fn create_summary_message(
+    /// Inserts the scratchpad of `steps` into `inputs`, trimming the chat
+    /// history so that the prompt fits the context budget.
+    async fn prepare_inputs(
         &self,
-        old_steps: &[impl IntermediateStep],
-    ) -> Result<Message, AgentError> {
-        let summary = format!(
-            "Previous {} steps summary: [Summarized execution history with {} actions completed]",
-            old_steps.len(),
-            old_steps.len()
-        );
+        steps: &[impl IntermediateStep],
+        inputs: &PromptArgs,
+    ) -> Result<PromptArgs, AgentError> {
+        let mut inputs = inputs.clone();
+        inputs
+            .entry("pinned_resources".to_string())
+            .or_insert_with(|| json!([]));
+        let scratchpad = self.fit_to_context(steps, &mut inputs).await?;
+        inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
+        Ok(inputs)
+    }
 
-        Ok(Message::new_system_message(&summary))
+    async fn fit_to_context(
+        &self,
+        steps: &[impl IntermediateStep],
+        inputs: &mut PromptArgs,
+    ) -> Result<Vec<Message>, AgentError> {
+        let (prefix, tools_tokens) = {
+            let state = self.state.read().unwrap();
+            (
+                state.prefix.clone(),
+                self.context.count_tools(&state.registry),
+            )
+        };
+        self.context.fit(&prefix, tools_tokens, steps, inputs).await
     }
 
     fn process_chunk_delta(
@@ -104,8 +306,9 @@ impl OpenAIMcpAgent {
             }
         } else if let Some(_tool_calls) = delta.get("tool_calls").and_then(|tc| tc.as_array()) {
             *has_tool_calls = true;
-            let tool_call_chunk = tool_call_acc.accumulate(delta);
-            events.push(AgentEventChunk::Delta(DeltaEvent::Action(tool_call_chunk)));
+            for tool_call_chunk in tool_call_acc.accumulate(delta) {
+                events.push(AgentEventChunk::Delta(DeltaEvent::Action(tool_call_chunk)));
+            }
         }
 
         // Handle finish reason
@@ -129,14 +332,10 @@ impl OpenAIMcpAgent {
         model_output: &str,
     ) -> AgentEvent {
         match finish_reason {
-            "tool_calls" => {
-                let action = tool_call_acc.take_action();
-                AgentEvent::Action(vec![action])
-            }
+            "tool_calls" => AgentEvent::Action(tool_call_acc.take_actions()),
             "stop" => {
                 if has_tool_calls {
-                    let action = tool_call_acc.take_action();
-                    AgentEvent::Action(vec![action])
+                    AgentEvent::Action(tool_call_acc.take_actions())
                 } else {
                     AgentEvent::Finish(AgentFinish {
                         output: model_output.to_string(),
@@ -157,10 +356,8 @@ impl Agent for OpenAIMcpAgent {
         intermediate_steps: &[(AgentAction, String)],
         inputs: PromptArgs,
     ) -> Result<AgentEvent, AgentError> {
-        let mut inputs = inputs.clone();
-        let scratchpad = self.construct_scratchpad(intermediate_steps)?;
-        inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
-        let output = self.chain.call(inputs).await?.generation;
+        let inputs = self.prepare_inputs(intermediate_steps, &inputs).await?;
+        let output = self.chain().call(inputs).await?.generation;
 
         match serde_json::from_str::<Vec<FunctionCallResponse>>(&output) {
             Ok(tools) => {
@@ -184,21 +381,62 @@ impl Agent for OpenAIMcpAgent {
     }
 
     fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
-        self.tools.clone()
+        self.state
+            .read()
+            .unwrap()
+            .registry
+            .iter()
+            .map(|entry| entry.tool.clone() as Arc<dyn Tool>)
+            .collect()
     }
 }
 
 #[async_trait]
 impl AgentExt for OpenAIMcpAgent {
+    fn tool_registry(&self) -> ToolRegistry {
+        self.state.read().unwrap().registry.clone()
+    }
+
+    fn sampler(&self) -> Option<Sampler> {
+        self.sampler.clone()
+    }
+
+    fn elicitation(&self) -> Option<ElicitationHandle> {
+        self.elicitation.clone()
+    }
+
+    async fn pinned_resources(&self) -> Vec<Message> {
+        self.toolset.lock().await.read_pinned_resources().await
+    }
+
+    async fn refresh_tools(&self) -> Result<bool, AgentError> {
+        let mut toolset = self.toolset.lock().await;
+        if !toolset.refresh().await? {
+            return Ok(false);
+        }
+
+        // On failure the toolset stays pending, so the next run retries.
+        let registry = toolset.build_registry()?;
+        {
+            let mut state = self.state.write().unwrap();
+            state.chain = self.chain_config.build_chain(&state.prefix, &registry)?;
+            state.registry = registry;
+            tracing::info!(
+                "Tool list refreshed, {} tools available",
+                state.registry.len()
+            );
+        }
+        toolset.mark_rebuilt();
+        Ok(true)
+    }
+
     async fn plan_with_steps(
         &self,
         steps: &[impl IntermediateStep],
         inputs: PromptArgs,
     ) -> Result<AgentEvent, AgentError> {
-        let mut inputs = inputs.clone();
-        let scratchpad = self.construct_scratchpad(steps)?;
-        inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
-        let output = self.chain.call(inputs).await?.generation;
+        let inputs = self.prepare_inputs(steps, &inputs).await?;
+        let output = self.chain().call(inputs).await?.generation;
 
         match serde_json::from_str::<Vec<FunctionCallResponse>>(&output) {
             Ok(tools) => {
@@ -229,11 +467,9 @@ impl AgentExt for OpenAIMcpAgent {
         use async_stream::stream;
         use futures_util::StreamExt;
 
-        let mut inputs = inputs.clone();
-        let scratchpad = self.construct_scratchpad(steps)?;
-        inputs.insert("agent_scratchpad".to_string(), json!(scratchpad));
+        let inputs = self.prepare_inputs(steps, &inputs).await?;
 
-        let mut chain_stream = self.chain.stream(inputs).await?;
+        let mut chain_stream = self.chain().stream(inputs).await?;
         let mut model_output = String::new();
         let mut tool_call_acc = ToolCallAccumulator::new();
         let mut has_tool_calls = false;
@@ -263,10 +499,7 @@ impl AgentExt for OpenAIMcpAgent {
             }
 
             match has_tool_calls {
-                true => {
-                    let action = tool_call_acc.take_action();
-                    yield Ok(AgentEventChunk::Final(AgentEvent::Action(vec![action])))
-                },
+                true => yield Ok(AgentEventChunk::Final(AgentEvent::Action(tool_call_acc.take_actions()))),
                 false => yield Ok(AgentEventChunk::Final(AgentEvent::Finish(AgentFinish { output: model_output })))
             }
         };
@@ -275,92 +508,253 @@ impl AgentExt for OpenAIMcpAgent {
     }
 }
 
-struct ToolCallAccumulator {
+/// A tool call being streamed, identified by its `index`.
+#[derive(Default)]
+struct PartialToolCall {
+    id: Option<String>,
     name: Option<String>,
     args: String,
-    id: Option<String>,
+}
+
+impl PartialToolCall {
+    fn function_call(&self, args: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id.clone(),
+            "type": "function",
+            "function": {
+                "name": self.name.clone(),
+                "arguments": args
+            }
+        })
+    }
+
+    /// An action for this call, whose log lists `tools` like the
+    /// non-streaming `plan` does.
+    fn to_action(&self, args: String, tools: &serde_json::Value) -> AgentAction {
+        let tools_output = serde_json::to_string(tools).unwrap_or_else(|_| {
+            "[{{\"error\": \"Failed to serialize function call\"}}]".to_string()
+        });
+
+        let log_tools = LogTools {
+            tool_id: self.id.clone().unwrap_or_default(),
+            tools: tools_output,
+        };
+
+        let log_str = serde_json::to_string(&log_tools).unwrap_or_else(|_| {
+            // If serialization fails, return a simple format
+            format!(
+                "{{\"tool_id\": \"{}\", \"tools\": \"[]\"}}",
+                self.id.clone().unwrap_or_default()
+            )
+        });
+
+        AgentAction {
+            tool: self.name.clone().unwrap_or_default(),
+            tool_input: args,
+            log: log_str,
+        }
+    }
+}
+
+/// Collects streamed tool calls. OpenAI streams parallel calls interleaved,
+/// each chunk carrying the `index` of the call it belongs to.
+struct ToolCallAccumulator {
+    calls: BTreeMap<u64, PartialToolCall>,
 }
 
 impl ToolCallAccumulator {
     fn new() -> Self {
         Self {
-            name: None,
-            args: String::new(),
-            id: None,
+            calls: BTreeMap::new(),
         }
     }
 
-    fn accumulate(&mut self, delta: &serde_json::Value) -> AgentAction {
-        let mut args_chunk = String::default();
+    /// Adds the tool call chunks of `delta`, returning one partial action per
+    /// chunk with the arguments received in it.
+    fn accumulate(&mut self, delta: &serde_json::Value) -> Vec<AgentAction> {
+        let Some(tool_calls) = delta.get("tool_calls").and_then(|v| v.as_array()) else {
+            return Vec::new();
+        };
+
+        let mut chunks = Vec::with_capacity(tool_calls.len());
+        for (position, tool_call) in tool_calls.iter().enumerate() {
+            let index = tool_call
+                .get("index")
+                .and_then(|i| i.as_u64())
+                .unwrap_or(position as u64);
+            let call = self.calls.entry(index).or_default();
 
-        if let Some(tool_call) = delta
-            .get("tool_calls")
-            .and_then(|v| v.as_array())
-            .and_then(|v| v.first())
-        {
+            let mut args_chunk = String::default();
             if let Some(function) = tool_call.get("function") {
                 if let Some(name) = function.get("name").and_then(|n| n.as_str()) {
-                    self.name = Some(name.to_string());
+                    call.name = Some(name.to_string());
                 }
                 if let Some(args) = function.get("arguments").and_then(|a| a.as_str()) {
-                    self.args.push_str(args);
+                    call.args.push_str(args);
                     args_chunk = args.to_string();
                 }
             }
-            if let Some(id) = tool_call.get("id").and_then(|i| i.as_str()) {
-                if !id.is_empty() {
-                    self.id = Some(id.to_string());
-                }
+            if let Some(id) = tool_call.get("id").and_then(|i| i.as_str())
+                && !id.is_empty()
+            {
+                call.id = Some(id.to_string());
             }
-        };
 
-        self.to_action_chunk(args_chunk)
+            let args_chunk = processed_args(args_chunk);
+            let tools = serde_json::json!([call.function_call(&args_chunk)]);
+            chunks.push(call.to_action(args_chunk, &tools));
+        }
+        chunks
     }
 
-    fn take_action(&mut self) -> AgentAction {
-        let args = std::mem::take(&mut self.args);
-        self.to_action_chunk(args)
+    /// Completed actions for every call, in index order.
+    fn take_actions(&mut self) -> Vec<AgentAction> {
+        let calls = std::mem::take(&mut self.calls)
+            .into_values()
+            .map(|mut call| {
+                let args = processed_args(std::mem::take(&mut call.args));
+                (call, args)
+            })
+            .collect::<Vec<_>>();
+
+        let tools = serde_json::Value::Array(
+            calls
+                .iter()
+                .map(|(call, args)| call.function_call(args))
+                .collect(),
+        );
+        calls
+            .into_iter()
+            .map(|(call, args)| call.to_action(args, &tools))
+            .collect()
     }
+}
 
-    fn to_action_chunk(&self, args_chunk: String) -> AgentAction {
-        let processed_args = if args_chunk.trim().is_empty() {
-            "{}".to_string()
-        } else {
-            args_chunk
-        };
+fn processed_args(args: String) -> String {
+    if args.trim().is_empty() {
+        "{}".to_string()
+    } else {
+        args
+    }
+}
 
-        let function_call_response = serde_json::json!({
-            "id": self.id.clone(),
-            "type": "function",
-            "function": {
-                "name": self.name.clone(),
-                "arguments": processed_args
-            }
-        });
+#[cfg(test)]
+mod tests {
+    use langchain_rust::schemas::MessageType;
+    use rmcp::model::Prompt;
 
-        // Construct tool call array (consistent with non-streaming method)
-        let tools_array = serde_json::json!([function_call_response]);
-        let tools_output = serde_json::to_string(&tools_array).unwrap_or_else(|_| {
-            "[{{\"error\": \"Failed to serialize function call\"}}]".to_string()
-        });
+    use super::*;
+    use crate::agent::builder::OpenAIMcpAgentBuilder;
+    use crate::client::testing::{StubServer, connect};
 
-        let log_tools = LogTools {
-            tool_id: self.id.clone().unwrap_or_default(),
-            tools: tools_output,
-        };
+    fn delta(index: u64, id: Option<&str>, name: Option<&str>, args: &str) -> serde_json::Value {
+        let mut function = json!({ "arguments": args });
+        if let Some(name) = name {
+            function["name"] = json!(name);
+        }
+        let mut call = json!({ "index": index, "function": function });
+        if let Some(id) = id {
+            call["id"] = json!(id);
+        }
+        json!({ "tool_calls": [call] })
+    }
 
-        let log_str = serde_json::to_string(&log_tools).unwrap_or_else(|_| {
-            // If serialization fails, return a simple format
-            format!(
-                "{{\"tool_id\": \"{}\", \"tools\": \"[]\"}}",
-                self.id.clone().unwrap_or_default()
-            )
+    #[test]
+    fn accumulates_interleaved_tool_calls() {
+        let mut acc = ToolCallAccumulator::new();
+        let deltas = [
+            delta(1, Some("call_b"), Some("read"), ""),
+            delta(0, Some("call_a"), Some("search"), "{\"q\":"),
+            delta(1, None, None, "{\"uri\":\"a\"}"),
+            delta(0, None, None, "\"rust\"}"),
+            delta(2, Some("call_c"), Some("list"), ""),
+        ];
+        for delta in &deltas {
+            let chunks = acc.accumulate(delta);
+            assert_eq!(chunks.len(), 1);
+        }
+
+        let actions = acc.take_actions();
+        let calls = actions
+            .iter()
+            .map(|action| {
+                let log = serde_json::from_str::<LogTools>(&action.log).unwrap();
+                (
+                    log.tool_id,
+                    action.tool.as_str(),
+                    action.tool_input.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            calls,
+            [
+                ("call_a".to_string(), "search", "{\"q\":\"rust\"}"),
+                ("call_b".to_string(), "read", "{\"uri\":\"a\"}"),
+                ("call_c".to_string(), "list", "{}"),
+            ]
+        );
+
+        // Every action lists the whole turn, as `plan` does.
+        let log = serde_json::from_str::<LogTools>(&actions[2].log).unwrap();
+        let tools = serde_json::from_str::<Vec<FunctionCallResponse>>(&log.tools).unwrap();
+        let ids = tools.iter().map(|t| t.id.as_str()).collect::<Vec<_>>();
+        assert_eq!(ids, ["call_a", "call_b", "call_c"]);
+        assert_eq!(tools[0].function.arguments, "{\"q\":\"rust\"}");
+
+        assert!(acc.take_actions().is_empty());
+    }
+
+    #[tokio::test]
+    async fn streaming_callback_survives_chain_rebuilds() {
+        let chunks = Arc::new(Mutex::new(Vec::new()));
+        let options = ChainCallOptions::new().with_streaming_func({
+            let chunks = chunks.clone();
+            move |chunk: String| {
+                chunks.lock().unwrap().push(chunk);
+                async { Ok(()) }
+            }
         });
+        let options = ChainOptions::from(options.with_max_tokens(64));
 
-        AgentAction {
-            tool: self.name.clone().unwrap_or_default(),
-            tool_input: processed_args,
-            log: log_str,
+        for chunk in ["a", "b"] {
+            let mut call_options = options.clone().to_call_options();
+            assert_eq!(call_options.max_tokens, Some(64));
+            let func = call_options.streaming_func.as_mut().unwrap();
+            func(chunk.to_string()).await.unwrap();
         }
+        assert_eq!(*chunks.lock().unwrap(), ["a", "b"]);
+    }
+
+    #[tokio::test]
+    async fn prompt_presets_replace_the_prefix() {
+        let server = StubServer {
+            prompts: vec![Prompt::new("review", None::<String>, None)],
+            ..Default::default()
+        };
+        let (client, server) = connect(server).await;
+        tokio::spawn(server.waiting());
+        let presets = McpPromptLibrary::load(Arc::new(client)).await.unwrap();
+        let agent = OpenAIMcpAgentBuilder::new("key", "http://127.0.0.1:1", "gpt-4o")
+            .prefix("You are helpful.")
+            .prompt_presets(presets)
+            .build()
+            .unwrap();
+
+        let prefix = || agent.state.read().unwrap().prefix.clone();
+        assert_eq!(prefix().len(), 1);
+        assert_eq!(prefix()[0].message_type, MessageType::SystemMessage);
+
+        agent.use_prompt_preset("review", None).await.unwrap();
+        let roles = prefix()
+            .into_iter()
+            .map(|m| m.message_type)
+            .collect::<Vec<_>>();
+        assert_eq!(roles, [MessageType::AIMessage, MessageType::HumanMessage]);
+        assert_eq!(prefix()[0].content, "You follow the `review` prompt.");
+
+        // A failed switch keeps the current prefix.
+        assert!(agent.use_prompt_preset("summary", None).await.is_err());
+        assert_eq!(prefix()[0].content, "You follow the `review` prompt.");
     }
 }
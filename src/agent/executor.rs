@@ -4,20 +4,46 @@ use std::sync::Arc;
 
 use async_trait::async_trait;
 use chrono::Utc;
-use futures_util::Stream;
+use futures_util::future::BoxFuture;
+use futures_util::{FutureExt, Stream, StreamExt};
 use langchain_rust::agent::AgentError;
 use langchain_rust::chain::{Chain, ChainError};
 use langchain_rust::language_models::GenerateResult;
 use langchain_rust::memory::SimpleMemory;
 use langchain_rust::prompt::PromptArgs;
-use langchain_rust::schemas::{AgentAction, AgentEvent, BaseMemory, LogTools, Message, StreamData};
-use langchain_rust::tools::Tool;
+use langchain_rust::schemas::{AgentAction, AgentEvent, BaseMemory, LogTools, StreamData};
 use serde_json::{Value, json};
 use tokio::sync::Mutex;
+use tokio::sync::mpsc::UnboundedSender;
+use tokio::task::JoinHandle;
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use uuid::Uuid;
 
+use crate::agent::approval::{ApprovalGate, ApprovalHandle, ApprovalPolicy, record_arguments};
 use crate::agent::extension::{AgentEventChunk, AgentExt, DeltaEvent};
+use crate::agent::intermediate::IntermediateStep;
+use crate::client::elicitation::ElicitationHandle;
+use crate::client::sampling::{RunId, Sampler, SamplingEvent, SamplingScope};
+use crate::tool::extension::{ToolCallContext, ToolError, ToolExt, ToolOutput};
+
+/// Finish reason of the delta announcing a tool call the agent is about to
+/// make. Deltas of calls still being generated have none.
+pub(crate) const FINISH_TOOL_CALL: &str = "";
+/// Finish reason of the last chunk of a run, whether it succeeded or failed.
+pub(crate) const FINISH_STOP: &str = "stop";
+/// Finish reason of the last chunk of a run stopped after `max_iterations`.
+pub(crate) const FINISH_LENGTH: &str = "length";
+
+/// Whether a streamed delta carries the result of a tool call.
+pub(crate) fn is_tool_result(delta: &Value) -> bool {
+    delta.get("parsed").is_some()
+}
+
+/// Whether a streamed delta reports an error ending the run. Failed tool
+/// results also carry `is_error`, but they go back to the model.
+pub(crate) fn is_run_error(delta: &Value) -> bool {
+    delta["is_error"] == true && !is_tool_result(delta)
+}
 
 pub struct OpenAIMcpAgentExecutor<A>
 where
@@ -26,6 +52,11 @@ where
     agent: Arc<A>,
     max_iterations: Option<i32>,
     break_if_error: bool,
+    /// Tool calls of the same turn run at most this many at a time.
+    max_concurrent_tools: usize,
+    approval: Option<ApprovalGate>,
+    elicitation: Option<ElicitationHandle>,
+    sampling_events: Option<UnboundedSender<SamplingEvent>>,
 
     pub model: String,
     pub memory: Option<Arc<Mutex<dyn BaseMemory>>>,
@@ -37,6 +68,10 @@ impl<A: AgentExt> OpenAIMcpAgentExecutor<A> {
             agent,
             max_iterations: Some(10),
             break_if_error: false,
+            max_concurrent_tools: 1,
+            approval: None,
+            elicitation: None,
+            sampling_events: None,
             memory: None,
             model: model.to_string(),
         }
@@ -52,19 +87,64 @@ impl<A: AgentExt> OpenAIMcpAgentExecutor<A> {
         self
     }
 
+    /// Ends the run when a tool call fails, whether the server cannot be
+    /// reached or the tool reports an error. Timeouts, invalid arguments and
+    /// rejected calls are still handed to the model.
     pub fn with_break_if_error(mut self, break_if_error: bool) -> Self {
         self.break_if_error = break_if_error;
         self
     }
 
-    fn get_name_to_tools(&self) -> HashMap<String, Arc<dyn Tool>> {
+    /// Runs up to `limit` of the tool calls the model makes in one turn
+    /// concurrently. Observations keep the order of the calls. Defaults to 1,
+    /// i.e. one call after the other.
+    pub fn with_max_concurrent_tools(mut self, limit: usize) -> Self {
+        self.max_concurrent_tools = limit.max(1);
+        self
+    }
+
+    /// Pauses before tool calls selected by `policy` until they are approved,
+    /// rejected or edited through `handle`.
+    pub fn with_approval(mut self, policy: ApprovalPolicy, handle: ApprovalHandle) -> Self {
+        self.approval = Some(ApprovalGate::new(policy, handle));
+        self
+    }
+
+    /// Sends the sampling requests MCP servers make during a run to
+    /// `events`. Streaming runs also report them as `sampling` deltas; this is
+    /// how `call` runs surface them.
+    pub fn with_sampling_events(mut self, events: UnboundedSender<SamplingEvent>) -> Self {
+        self.sampling_events = Some(events);
+        self
+    }
+
+    /// Reports the elicitation requests made through `handle` during
+    /// streaming runs as `elicitation_required` deltas. Tool calls waiting
+    /// for the answer hold the run until it is given through `handle`.
+    /// Defaults to the agent's handle, see [`AgentExt::elicitation`].
+    pub fn with_elicitation(mut self, handle: ElicitationHandle) -> Self {
+        self.elicitation = Some(handle);
+        self
+    }
+
+    fn get_name_to_tools(agent: &A) -> HashMap<String, Arc<dyn ToolExt>> {
         let mut name_to_tool = HashMap::new();
-        for tool in self.agent.get_tools().iter() {
-            tracing::debug!("Loading Tool: {}", tool.name());
-            name_to_tool.insert(tool.name().trim().replace(" ", "_"), tool.clone());
+        for entry in agent.tool_registry().iter() {
+            tracing::debug!("Loading Tool: {} from {}", entry.name, entry.source);
+            name_to_tool.insert(entry.name.clone(), entry.tool.clone());
         }
         name_to_tool
     }
+
+    /// Re-reads the agent's tools if an MCP server changed its tool list.
+    /// Failures are logged and the current tools are kept.
+    async fn refresh_tools(agent: &A, name_to_tools: &mut HashMap<String, Arc<dyn ToolExt>>) {
+        match agent.refresh_tools().await {
+            Ok(true) => *name_to_tools = Self::get_name_to_tools(agent),
+            Ok(false) => {}
+            Err(e) => tracing::warn!("Failed to refresh tools, keeping the current ones: {e}"),
+        }
+    }
 }
 
 #[async_trait]
@@ -74,8 +154,8 @@ where
 {
     async fn call(&self, input_variables: PromptArgs) -> Result<GenerateResult, ChainError> {
         let mut input_variables = input_variables.clone();
-        let name_to_tools = self.get_name_to_tools();
-        let mut steps: Vec<(AgentAction, String)> = Vec::new();
+        let mut name_to_tools = Self::get_name_to_tools(&self.agent);
+        let mut steps: Vec<(AgentAction, ToolOutput)> = Vec::new();
         tracing::debug!("steps: {steps:?}");
         if let Some(memory) = &self.memory {
             let memory = memory.lock().await;
@@ -86,42 +166,119 @@ where
                 json!(SimpleMemory::new().messages()),
             );
         }
+        input_variables.insert(
+            "pinned_resources".to_string(),
+            json!(self.agent.pinned_resources().await),
+        );
+        let sampling_run = self.agent.sampler().map(|sampler| {
+            let run = sampler.begin_run(self.sampling_events.clone());
+            SamplingRun::new(sampler, run, None)
+        });
+        let sampling = sampling_run.as_ref().map(SamplingRun::scope);
 
         loop {
+            Self::refresh_tools(&self.agent, &mut name_to_tools).await;
+
             let agent_event = self
                 .agent
-                .plan(&steps, input_variables.clone())
+                .plan_with_steps(&steps, input_variables.clone())
                 .await
                 .map_err(|e| ChainError::AgentError(format!("Error in agent planning: {e}")))?;
 
             match agent_event {
                 AgentEvent::Action(actions) => {
+                    // Resolve every tool first, so that an unknown tool fails
+                    // the run before any of the calls is made.
+                    let mut calls = Vec::with_capacity(actions.len());
                     for action in actions {
                         tracing::debug!("Action: {:?}", action.tool_input);
                         let tool = name_to_tools
                             .get(&action.tool.trim().replace(" ", "_"))
+                            .cloned()
                             .ok_or_else(|| {
                                 AgentError::ToolError(format!("Tool {} not found", action.tool))
                             })
                             .map_err(|e| ChainError::AgentError(e.to_string()))?;
+                        calls.push((action, tool));
+                    }
+
+                    let approval = &self.approval;
+                    let sampling = &sampling;
+                    let break_if_error = self.break_if_error;
+                    // `buffered` runs up to `max_concurrent_tools` calls at once
+                    // but yields the results in call order.
+                    let calls: Vec<BoxFuture<'_, Result<(AgentAction, ToolOutput), ChainError>>> =
+                        calls
+                            .into_iter()
+                            .map(|(mut action, tool)| {
+                                async move {
+                                    let tool_call_id =
+                                        serde_json::from_str::<LogTools>(&action.log)
+                                            .map(|log| log.tool_id)
+                                            .unwrap_or_default();
+                                    let reviewed = match approval {
+                                        Some(gate) => {
+                                            gate.review(
+                                                tool.as_ref(),
+                                                &tool_call_id,
+                                                &mut action,
+                                                |_| {},
+                                            )
+                                            .await
+                                        }
+                                        None => Ok(()),
+                                    };
+                                    let ctx = ToolCallContext::new(tool_call_id)
+                                        .with_sampling(sampling.clone());
 
-                        let observation = match tool.call(&action.tool_input).await {
-                            Ok(result) => result,
-                            Err(err) => {
-                                let error_msg = err.to_string();
-                                tracing::info!("The tool return the following error: {error_msg}");
-                                if self.break_if_error {
-                                    return Err(ChainError::AgentError(
-                                        AgentError::ToolError(error_msg).to_string(),
-                                    ));
-                                } else {
-                                    format!("The tool return the following error: {error_msg}")
+                                    let result = match reviewed {
+                                        Ok(()) => tool.call_ext(&action.tool_input, ctx).await,
+                                        Err(e) => Err(e),
+                                    };
+                                    let observation = match result {
+                                        Ok(result) => result,
+                                        // Let the model fix its own arguments instead of aborting the run.
+                                        Err(ToolError::InvalidArguments(msg)) => {
+                                            tracing::info!("Rejected tool arguments: {msg}");
+                                            ToolOutput::from(msg)
+                                        }
+                                        Err(ToolError::Rejected(msg)) => {
+                                            tracing::info!("{msg}");
+                                            ToolOutput::from(msg)
+                                        }
+                                        Err(err) => {
+                                            tracing::info!(
+                                                "The tool return the following error: {err}"
+                                            );
+                                            // Timeouts go back to the model; other failures,
+                                            // including errors reported by the tool, end the run.
+                                            if break_if_error && !err.is_recoverable() {
+                                                return Err(ChainError::AgentError(
+                                                    AgentError::ToolError(err.to_string())
+                                                        .to_string(),
+                                                ));
+                                            }
+                                            err.into_observation()
+                                        }
+                                    };
+
+                                    Ok((action, observation))
                                 }
-                            }
-                        };
+                                .boxed()
+                            })
+                            .collect();
+                    let mut results =
+                        futures_util::stream::iter(calls).buffered(self.max_concurrent_tools);
 
-                        steps.push((action, observation));
+                    let turn_start = steps.len();
+                    while let Some(step) = results.next().await {
+                        steps.push(step?);
                     }
+                    let mut actions = steps[turn_start..]
+                        .iter_mut()
+                        .map(|(action, _)| action)
+                        .collect::<Vec<_>>();
+                    record_arguments(&mut actions);
                 }
                 AgentEvent::Finish(finish) => {
                     if let Some(memory) = &self.memory {
@@ -133,17 +290,7 @@ where
                             x => x, // This is the JSON encoded value.
                         });
 
-                        let mut tools_ai_message_seen: HashMap<String, ()> = HashMap::default();
-                        for (action, observation) in steps {
-                            let LogTools { tool_id, tools } = serde_json::from_str(&action.log)?;
-                            let tools_value = serde_json::from_str(&tools)?;
-                            if tools_ai_message_seen.insert(tools, ()).is_none() {
-                                memory.add_message(
-                                    Message::new_ai_message("").with_tool_calls(tools_value),
-                                );
-                            }
-                            memory.add_message(Message::new_tool_message(observation, tool_id));
-                        }
+                        add_steps(&mut *memory, &steps);
 
                         memory.add_ai_message(&finish.output);
                     }
@@ -155,13 +302,13 @@ where
                 }
             }
 
-            if let Some(max_iterations) = self.max_iterations {
-                if steps.len() >= max_iterations as usize {
-                    return Ok(GenerateResult {
-                        generation: "Max iterations reached".to_string(),
-                        ..Default::default()
-                    });
-                }
+            if let Some(max_iterations) = self.max_iterations
+                && steps.len() >= max_iterations as usize
+            {
+                return Ok(GenerateResult {
+                    generation: "Max iterations reached".to_string(),
+                    ..Default::default()
+                });
             }
         }
     }
@@ -172,8 +319,8 @@ where
     ) -> Result<Pin<Box<dyn Stream<Item = Result<StreamData, ChainError>> + Send>>, ChainError>
     {
         let mut input_variables = input_variables.clone();
-        let name_to_tools = self.get_name_to_tools();
-        let mut steps: Vec<(AgentAction, String)> = Vec::new();
+        let mut name_to_tools = Self::get_name_to_tools(&self.agent);
+        let mut steps: Vec<(AgentAction, ToolOutput)> = Vec::new();
 
         let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
 
@@ -186,6 +333,10 @@ where
                 json!(SimpleMemory::new().messages()),
             );
         }
+        input_variables.insert(
+            "pinned_resources".to_string(),
+            json!(self.agent.pinned_resources().await),
+        );
 
         let conversation_id = input_variables
             .get("conversation_id")
@@ -222,16 +373,106 @@ where
         let memory = self.memory.clone();
         let max_iterations = self.max_iterations;
         let break_if_error = self.break_if_error;
+        let max_concurrent_tools = self.max_concurrent_tools;
+        let approval = self.approval.clone();
+
+        // Sampling requests made by MCP servers during the run are reported on
+        // the stream. The forwarder only holds a weak sender so that it does not
+        // keep the stream open, and is stopped when the run ends.
+        let sampling_run = agent.sampler().map(|sampler| {
+            let (sampling_tx, mut sampling_rx) = tokio::sync::mpsc::unbounded_channel();
+            let run = sampler.begin_run(Some(sampling_tx));
+
+            let tx = tx.downgrade();
+            let events = self.sampling_events.clone();
+            let chat_completion_id = chat_completion_id.clone();
+            let conversation_id = conversation_id.clone();
+            let model = model.clone();
+            let forwarder = tokio::spawn(async move {
+                while let Some(event) = sampling_rx.recv().await {
+                    let Some(tx) = tx.upgrade() else {
+                        break;
+                    };
+                    if let Some(events) = &events {
+                        let _ = events.send(event.clone());
+                    }
+                    let _ = tx.send(Ok(StreamData::new(
+                        json!({
+                            "id": chat_completion_id,
+                            "conversation_id": conversation_id,
+                            "object": "chat.completion.chunk",
+                            "created": created,
+                            "model": model,
+                            "choices": [{
+                                "index": 0,
+                                "delta": {
+                                    "content": null,
+                                    "sampling": event
+                                },
+                                "logprobs": null,
+                                "finish_reason": null
+                            }]
+                        }),
+                        None,
+                        "",
+                    )));
+                }
+            });
+            SamplingRun::new(sampler, run, Some(forwarder))
+        });
+
+        // Elicitation requests are reported the same way. The run itself just
+        // keeps waiting on the tool call until the server gets its answer.
+        let elicitation = self.elicitation.clone().or_else(|| agent.elicitation());
+        let elicitation_run = elicitation.map(|handle| {
+            let (elicitation_tx, mut elicitation_rx) = tokio::sync::mpsc::unbounded_channel();
+
+            let tx = tx.downgrade();
+            let chat_completion_id = chat_completion_id.clone();
+            let conversation_id = conversation_id.clone();
+            let model = model.clone();
+            let forwarder = tokio::spawn(async move {
+                while let Some(request) = elicitation_rx.recv().await {
+                    let Some(tx) = tx.upgrade() else {
+                        break;
+                    };
+                    let _ = tx.send(Ok(StreamData::new(
+                        json!({
+                            "id": chat_completion_id,
+                            "conversation_id": conversation_id,
+                            "object": "chat.completion.chunk",
+                            "created": created,
+                            "model": model,
+                            "choices": [{
+                                "index": 0,
+                                "delta": {
+                                    "content": null,
+                                    "elicitation_required": request
+                                },
+                                "logprobs": null,
+                                "finish_reason": null
+                            }]
+                        }),
+                        None,
+                        "",
+                    )));
+                }
+            });
+            handle.begin_run(elicitation_tx, Some(forwarder))
+        });
 
         tokio::spawn(async move {
-            use futures_util::StreamExt;
+            let sampling = sampling_run.as_ref().map(SamplingRun::scope);
+            let _sampling_run = sampling_run;
+            let _elicitation_run = elicitation_run;
 
             let mut accumulated_content = String::new();
-            let mut current_iteration_steps: Vec<(AgentAction, String)> = Vec::new();
+            let mut current_iteration_steps: Vec<(AgentAction, ToolOutput)> = Vec::new();
 
             loop {
                 accumulated_content.clear();
                 current_iteration_steps.clear();
+                Self::refresh_tools(&agent, &mut name_to_tools).await;
 
                 let mut plan_stream = match agent.plan_stream(&steps, input_variables.clone()).await
                 {
@@ -247,10 +488,11 @@ where
                                 "choices": [{
                                     "index": 0,
                                     "delta": {
-                                        "content": format!("Error: {e}")
+                                        "content": format!("Error: {e}"),
+                                        "is_error": true
                                     },
                                     "logprobs": null,
-                                    "finish_reason": "stop"
+                                    "finish_reason": FINISH_STOP
                                 }]
                             }),
                             None,
@@ -351,11 +593,14 @@ where
                                 tracing::debug!("got event: {event:?}");
                                 match event {
                                     AgentEvent::Action(actions) => {
+                                        // Resolve every tool first, so that an unknown tool
+                                        // ends the run before any of the calls is made.
+                                        let mut calls = Vec::with_capacity(actions.len());
                                         for action in actions {
                                             let tool = match name_to_tools
                                                 .get(&action.tool.trim().replace(" ", "_"))
                                             {
-                                                Some(tool) => tool,
+                                                Some(tool) => tool.clone(),
                                                 None => {
                                                     let error_msg =
                                                         format!("Tool {} not found", action.tool);
@@ -369,10 +614,11 @@ where
                                                             "choices": [{
                                                                 "index": 0,
                                                                 "delta": {
-                                                                    "content": error_msg
+                                                                    "content": error_msg,
+                                                                    "is_error": true
                                                                 },
                                                                 "logprobs": null,
-                                                                "finish_reason": "stop"
+                                                                "finish_reason": FINISH_STOP
                                                             }]
                                                         }),
                                                         None,
@@ -382,6 +628,19 @@ where
                                                 }
                                             };
 
+                                            calls.push((action, tool));
+                                        }
+
+                                        let tx = &tx;
+                                        let approval = &approval;
+                                        let sampling = &sampling;
+                                        let chat_completion_id = &chat_completion_id;
+                                        let conversation_id = &conversation_id;
+                                        let model = &model;
+                                        // Each call streams its deltas as it runs, while `buffered`
+                                        // hands the observations back in call order. The futures
+                                        // are boxed up front so the spawned task stays `Send`.
+                                        let calls: Vec<BoxFuture<'_, Option<(AgentAction, ToolOutput)>>> = calls.into_iter().map(|(mut action, tool)| async move {
                                             let log: Value = serde_json::from_str(&action.log)
                                                 .unwrap_or_default();
 
@@ -391,6 +650,48 @@ where
                                                 .map(|s| s.to_string())
                                                 .unwrap_or_else(|| Uuid::now_v7().to_string());
 
+                                            let reviewed = match &approval {
+                                                Some(gate) => {
+                                                    let review = gate.review(
+                                                        tool.as_ref(),
+                                                        &tool_call_id,
+                                                        &mut action,
+                                                        |request| {
+                                                            let _ = tx.send(Ok(StreamData::new(
+                                                                json!({
+                                                                    "id": chat_completion_id,
+                                                                    "conversation_id": conversation_id,
+                                                                    "object": "chat.completion.chunk",
+                                                                    "created": created,
+                                                                    "model": model,
+                                                                    "choices": [{
+                                                                        "index": 0,
+                                                                        "delta": {
+                                                                            "content": null,
+                                                                            "approval_required": request,
+                                                                            "tool_name": tool.name(),
+                                                                            "tool_call_id": tool_call_id
+                                                                        },
+                                                                        "logprobs": null,
+                                                                        "finish_reason": null
+                                                                    }]
+                                                                }),
+                                                                None,
+                                                                "",
+                                                            )));
+                                                        },
+                                                    );
+                                                    // Nobody is left to answer once the stream is
+                                                    // dropped, so the run ends there.
+                                                    tokio::select! {
+                                                        reviewed = review => reviewed,
+                                                        _ = tx.closed() => return None,
+                                                    }
+                                                }
+                                                None => Ok(()),
+                                            };
+
+                                            // Sent once reviewed, with the arguments the tool runs with.
                                             let tool_call_json = json!({
                                                 "id": tool_call_id,
                                                 "conversation_id": conversation_id,
@@ -414,62 +715,139 @@ where
                                                             "tool_calls": [tool_call_json]
                                                         },
                                                         "logprobs": null,
-                                                        "finish_reason": ""
+                                                        "finish_reason": FINISH_TOOL_CALL
                                                     }]
                                                 }),
                                                 None,
                                                 "",
                                             )));
 
-                                            let observation =
-                                                match tool.call(&action.tool_input).await {
-                                                    Ok(result) => result,
-                                                    Err(err) => {
-                                                        let error_msg =
-                                                            format!("Tool error: {err}");
-
-                                                        if break_if_error {
+                                            // Forward MCP progress notifications while the tool runs.
+                                            let (progress_tx, mut progress_rx) =
+                                                tokio::sync::mpsc::unbounded_channel();
+                                            let ctx = ToolCallContext::new(&tool_call_id)
+                                                .with_progress(progress_tx)
+                                                .with_sampling(sampling.clone());
+                                            let result = if let Err(e) = reviewed {
+                                                Err(e)
+                                            } else {
+                                                let call = tool.call_ext(&action.tool_input, ctx);
+                                                tokio::pin!(call);
+                                                loop {
+                                                    tokio::select! {
+                                                        result = &mut call => break result,
+                                                        Some(progress) = progress_rx.recv() => {
                                                             let _ = tx.send(Ok(StreamData::new(
-                                                            json!({
-                                                                "id": chat_completion_id,
-                                                                "conversation_id": conversation_id,
-                                                                "object": "chat.completion.chunk",
-                                                                "created": created,
-                                                                "model": model,
-                                                                "choices": [{
-                                                                    "index": 0,
-                                                                    "delta": {
-                                                                        "content": error_msg
-                                                                    },
-                                                                    "logprobs": null,
-                                                                    "finish_reason": "stop"
-                                                                }]
-                                                            }),
-                                                            None,
-                                                            error_msg,
-                                                        )));
-                                                            return;
-                                                        } else {
-                                                            error_msg
+                                                                json!({
+                                                                    "id": chat_completion_id,
+                                                                    "conversation_id": conversation_id,
+                                                                    "object": "chat.completion.chunk",
+                                                                    "created": created,
+                                                                    "model": model,
+                                                                    "choices": [{
+                                                                        "index": 0,
+                                                                        "delta": {
+                                                                            "content": null,
+                                                                            "tool_progress": progress,
+                                                                            "tool_name": tool.name(),
+                                                                            "tool_call_id": tool_call_id
+                                                                        },
+                                                                        "logprobs": null,
+                                                                        "finish_reason": null
+                                                                    }]
+                                                                }),
+                                                                None,
+                                                                "",
+                                                            )));
                                                         }
                                                     }
-                                                };
-
-                                            let parsed = match serde_json::from_str::<Value>(
-                                                &observation,
-                                            ) {
-                                                Ok(json_result) => json_result,
-                                                Err(e) => {
-                                                    tracing::warn!(
-                                                        "got error in parsing resp: {e:?}, raw: {observation}"
-                                                    );
-                                                    Value::String(observation.clone())
                                                 }
                                             };
 
+                                            let (observation, is_error) = match result {
+                                                Ok(result) => (result, false),
+                                                Err(
+                                                    ToolError::InvalidArguments(msg)
+                                                    | ToolError::Rejected(msg),
+                                                ) => (ToolOutput::from(msg), true),
+                                                // Timeouts go back to the model; other failures,
+                                                // including errors reported by the tool, end the run.
+                                                Err(err)
+                                                    if err.is_recoverable() || !break_if_error =>
+                                                {
+                                                    (err.into_observation(), true)
+                                                }
+                                                Err(err) => {
+                                                    let error_msg = format!("Tool error: {err}");
+
+                                                    let _ = tx.send(Ok(StreamData::new(
+                                                        json!({
+                                                            "id": chat_completion_id,
+                                                            "conversation_id": conversation_id,
+                                                            "object": "chat.completion.chunk",
+                                                            "created": created,
+                                                            "model": model,
+                                                            "choices": [{
+                                                                "index": 0,
+                                                                "delta": {
+                                                                    "content": null,
+                                                                    "is_error": true,
+                                                                    "error_message": error_msg,
+                                                                    "tool_name": tool.name(),
+                                                                    "tool_call_id": tool_call_id
+                                                                },
+                                                                "logprobs": null,
+                                                                "finish_reason": null
+                                                            }]
+                                                        }),
+                                                        None,
+                                                        "",
+                                                    )));
+                                                    let _ = tx.send(Ok(StreamData::new(
+                                                        json!({
+                                                            "id": chat_completion_id,
+                                                            "conversation_id": conversation_id,
+                                                            "object": "chat.completion.chunk",
+                                                            "created": created,
+                                                            "model": model,
+                                                            "choices": [{
+                                                                "index": 0,
+                                                                "delta": {
+                                                                    "content": error_msg,
+                                                                    "is_error": true
+                                                                },
+                                                                "logprobs": null,
+                                                                "finish_reason": FINISH_STOP
+                                                            }]
+                                                        }),
+                                                        None,
+                                                        error_msg,
+                                                    )));
+                                                    return None;
+                                                }
+                                            };
+
+                                            let parsed = match &observation.structured {
+                                                Some(structured) => structured.clone(),
+                                                None => match serde_json::from_str::<Value>(
+                                                    &observation.content,
+                                                ) {
+                                                    Ok(json_result) => json_result,
+                                                    Err(e) => {
+                                                        tracing::warn!(
+                                                            "got error in parsing resp: {e:?}, raw: {}",
+                                                            observation.content
+                                                        );
+                                                        Value::String(observation.content.clone())
+                                                    }
+                                                },
+                                            };
+
                                             let delta = json!({
                                                 "content": null,
                                                 "parsed": parsed,
+                                                "parts": observation.parts,
+                                                "is_error": is_error,
                                                 "tool_name": tool.name(),
                                                 "tool_call_id": tool_call_id
                                             });
@@ -492,64 +870,41 @@ where
                                                 parsed.to_string(),
                                             )));
 
-                                            tracing::debug!("observation: {observation}");
+                                            tracing::debug!("observation: {}", observation.content);
 
+                                            Some((action, observation))
+                                        }.boxed()).collect();
+                                        let mut results = futures_util::stream::iter(calls)
+                                            .buffered(max_concurrent_tools);
+
+                                        let mut turn = Vec::new();
+                                        while let Some(step) = results.next().await {
+                                            let Some(step) = step else {
+                                                return;
+                                            };
+                                            turn.push(step);
+                                        }
+                                        let mut actions = turn
+                                            .iter_mut()
+                                            .map(|(action, _)| action)
+                                            .collect::<Vec<_>>();
+                                        record_arguments(&mut actions);
+                                        for (action, observation) in turn {
                                             current_iteration_steps
                                                 .push((action.clone(), observation.clone()));
                                             steps.push((action, observation));
                                         }
 
-                                        if !accumulated_content.is_empty() {
-                                            if let Some(memory) = &memory {
-                                                let mut memory = memory.lock().await;
-                                                memory.add_ai_message(&accumulated_content);
-                                            }
+                                        if !accumulated_content.is_empty()
+                                            && let Some(memory) = &memory
+                                        {
+                                            let mut memory = memory.lock().await;
+                                            memory.add_ai_message(&accumulated_content);
                                         }
 
                                         if let Some(memory) = &memory {
                                             let mut memory = memory.lock().await;
-                                            let mut tools_ai_message_seen: HashMap<String, ()> =
-                                                HashMap::default();
-
-                                            for (action, observation) in &current_iteration_steps {
-                                                match serde_json::from_str::<LogTools>(&action.log)
-                                                {
-                                                    Ok(LogTools { tool_id, tools }) => {
-                                                        if let Ok(tools_value) =
-                                                            serde_json::from_str::<Value>(&tools)
-                                                        {
-                                                            if tools_ai_message_seen
-                                                                .insert(tools, ())
-                                                                .is_none()
-                                                            {
-                                                                memory.add_message(
-                                                                    Message::new_ai_message("")
-                                                                        .with_tool_calls(
-                                                                            tools_value,
-                                                                        ),
-                                                                );
-                                                            }
-                                                            memory.add_message(
-                                                                Message::new_tool_message(
-                                                                    observation.clone(),
-                                                                    tool_id,
-                                                                ),
-                                                            );
-                                                        } else {
-                                                            tracing::warn!(
-                                                                "Failed to parse tools JSON: {}",
-                                                                tools
-                                                            );
-                                                        }
-                                                    }
-                                                    Err(e) => {
-                                                        tracing::warn!(
-                                                            "Failed to parse action log: {}",
-                                                            e
-                                                        );
-                                                    }
-                                                }
-                                            }
+                                            add_steps(&mut *memory, &current_iteration_steps);
                                         }
 
                                         break;
@@ -573,45 +928,7 @@ where
                                                 memory.add_ai_message(&accumulated_content);
                                             }
 
-                                            let mut tools_ai_message_seen: HashMap<String, ()> =
-                                                HashMap::default();
-                                            for (action, observation) in &steps {
-                                                match serde_json::from_str::<LogTools>(&action.log)
-                                                {
-                                                    Ok(LogTools { tool_id, tools }) => {
-                                                        if let Ok(tools_value) =
-                                                            serde_json::from_str::<Value>(&tools)
-                                                        {
-                                                            if tools_ai_message_seen
-                                                                .insert(tools, ())
-                                                                .is_none()
-                                                            {
-                                                                memory.add_message(
-                                                                    Message::new_ai_message("")
-                                                                        .with_tool_calls(
-                                                                            tools_value,
-                                                                        ),
-                                                                );
-                                                            }
-                                                            memory.add_message(
-                                                                Message::new_tool_message(
-                                                                    observation.clone(),
-                                                                    tool_id,
-                                                                ),
-                                                            );
-                                                        } else {
-                                                            tracing::warn!(
-                                                                "Failed to parse tools JSON: {tools}"
-                                                            );
-                                                        }
-                                                    }
-                                                    Err(e) => {
-                                                        tracing::warn!(
-                                                            "Failed to parse action log: {e}"
-                                                        );
-                                                    }
-                                                }
-                                            }
+                                            add_steps(&mut *memory, &steps);
                                             memory.add_ai_message(&finish.output);
                                         }
 
@@ -626,7 +943,7 @@ where
                                                     "index": 0,
                                                     "delta": {},
                                                     "logprobs": null,
-                                                    "finish_reason": "stop"
+                                                    "finish_reason": FINISH_STOP
                                                 }]
                                             }),
                                             None,
@@ -648,10 +965,11 @@ where
                                     "choices": [{
                                         "index": 0,
                                         "delta": {
-                                            "content": format!("Stream error: {e}")
+                                            "content": format!("Stream error: {e}"),
+                                            "is_error": true
                                         },
                                         "logprobs": null,
-                                        "finish_reason": "stop"
+                                        "finish_reason": FINISH_STOP
                                     }]
                                 }),
                                 None,
@@ -669,29 +987,29 @@ where
                 }
 
                 // Check max iterations before continuing
-                if let Some(max_iterations) = max_iterations {
-                    if steps.len() >= max_iterations as usize {
-                        let _ = tx.send(Ok(StreamData::new(
-                            json!({
-                                "id": chat_completion_id,
-                                "conversation_id": conversation_id,
-                                "object": "chat.completion.chunk",
-                                "created": created,
-                                "model": model,
-                                "choices": [{
-                                    "index": 0,
-                                    "delta": {
-                                        "content": "Maximum iterations reached."
-                                    },
-                                    "logprobs": null,
-                                    "finish_reason": "length"
-                                }]
-                            }),
-                            None,
-                            "Maximum iterations reached.",
-                        )));
-                        return;
-                    }
+                if let Some(max_iterations) = max_iterations
+                    && steps.len() >= max_iterations as usize
+                {
+                    let _ = tx.send(Ok(StreamData::new(
+                        json!({
+                            "id": chat_completion_id,
+                            "conversation_id": conversation_id,
+                            "object": "chat.completion.chunk",
+                            "created": created,
+                            "model": model,
+                            "choices": [{
+                                "index": 0,
+                                "delta": {
+                                    "content": "Maximum iterations reached."
+                                },
+                                "logprobs": null,
+                                "finish_reason": FINISH_LENGTH
+                            }]
+                        }),
+                        None,
+                        "Maximum iterations reached.",
+                    )));
+                    return;
                 }
             }
         });
@@ -699,3 +1017,232 @@ where
         Ok(Box::pin(UnboundedReceiverStream::new(rx)))
     }
 }
+
+/// Adds the tool calls of `steps` and their results to `memory`, as they are
+/// sent to the model.
+fn add_steps(memory: &mut dyn BaseMemory, steps: &[(AgentAction, ToolOutput)]) {
+    let mut messages = Vec::new();
+    for step in steps {
+        if let Err(e) = step.append_to_conversation(&mut messages) {
+            tracing::warn!("Failed to add a tool call to memory: {e}");
+        }
+    }
+    for message in messages {
+        memory.add_message(message);
+    }
+}
+
+/// Ends a sampler's run when dropped, however the run finishes, and stops
+/// the task forwarding its events.
+struct SamplingRun {
+    sampler: Sampler,
+    run: RunId,
+    forwarder: Option<JoinHandle<()>>,
+}
+
+impl SamplingRun {
+    fn new(sampler: Sampler, run: RunId, forwarder: Option<JoinHandle<()>>) -> Self {
+        Self {
+            sampler,
+            run,
+            forwarder,
+        }
+    }
+
+    fn scope(&self) -> SamplingScope {
+        SamplingScope::new(self.sampler.clone(), self.run)
+    }
+}
+
+impl Drop for SamplingRun {
+    fn drop(&mut self) {
+        self.sampler.end_run(self.run);
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.abort();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use langchain_rust::prompt_args;
+    use langchain_rust::tools::Tool;
+
+    use super::*;
+    use crate::agent::extension::AgentStream;
+    use crate::client::testing::{StubReply, StubServer, connect, tool};
+    use crate::tool::native::FnTool;
+    use crate::tool::registry::{ToolRegistry, ToolSource};
+    use crate::tool::rmcp::{RmcpTool, RmcpToolOptions};
+
+    /// Calls `tool` once, then finishes with the observation it got.
+    struct OneCallAgent {
+        tool: Arc<dyn ToolExt>,
+    }
+
+    impl OneCallAgent {
+        fn failing_with(error: fn() -> ToolError) -> Self {
+            let tool = FnTool::new(
+                "lookup",
+                "Looks something up",
+                json!({ "type": "object" }),
+                move |_| async move { Err::<ToolOutput, _>(error()) },
+            );
+            Self {
+                tool: Arc::new(tool),
+            }
+        }
+
+        fn next_event(steps: &[impl IntermediateStep]) -> AgentEvent {
+            match steps.first() {
+                None => AgentEvent::Action(vec![AgentAction {
+                    tool: "lookup".to_string(),
+                    tool_input: "{}".to_string(),
+                    log: json!({
+                        "tool_id": "call_1",
+                        "tools": json!([{
+                            "id": "call_1",
+                            "type": "function",
+                            "function": { "name": "lookup", "arguments": "{}" }
+                        }])
+                        .to_string()
+                    })
+                    .to_string(),
+                }]),
+                Some(step) => AgentEvent::Finish(langchain_rust::schemas::AgentFinish {
+                    output: step.observation().to_string(),
+                }),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl langchain_rust::agent::Agent for OneCallAgent {
+        async fn plan(
+            &self,
+            steps: &[(AgentAction, String)],
+            _inputs: PromptArgs,
+        ) -> Result<AgentEvent, AgentError> {
+            Ok(Self::next_event(steps))
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn Tool>> {
+            vec![self.tool.clone()]
+        }
+    }
+
+    #[async_trait]
+    impl AgentExt for OneCallAgent {
+        fn tool_registry(&self) -> ToolRegistry {
+            let mut registry = ToolRegistry::new();
+            registry
+                .register(ToolSource::Native, self.tool.clone())
+                .unwrap();
+            registry
+        }
+
+        async fn plan_with_steps(
+            &self,
+            steps: &[impl IntermediateStep],
+            _inputs: PromptArgs,
+        ) -> Result<AgentEvent, AgentError> {
+            Ok(Self::next_event(steps))
+        }
+
+        async fn plan_stream(
+            &self,
+            steps: &[impl IntermediateStep],
+            _inputs: PromptArgs,
+        ) -> Result<AgentStream, AgentError> {
+            let event = AgentEventChunk::Final(Self::next_event(steps));
+            Ok(Box::pin(futures_util::stream::iter([Ok(event)])))
+        }
+    }
+
+    fn executor(agent: OneCallAgent, break_if_error: bool) -> OpenAIMcpAgentExecutor<OneCallAgent> {
+        OpenAIMcpAgentExecutor::new(Arc::new(agent), "test").with_break_if_error(break_if_error)
+    }
+
+    fn tool_error() -> ToolError {
+        ToolError::Tool(ToolOutput::from("no such record".to_string()))
+    }
+
+    fn timeout() -> ToolError {
+        ToolError::Timeout("lookup timed out".to_string())
+    }
+
+    #[tokio::test]
+    async fn tool_errors_end_the_run_when_breaking_on_errors() {
+        let result = executor(OneCallAgent::failing_with(tool_error), true)
+            .call(prompt_args! { "input" => "find it" })
+            .await;
+        assert!(result.is_err());
+
+        let result = executor(OneCallAgent::failing_with(tool_error), false)
+            .call(prompt_args! { "input" => "find it" })
+            .await
+            .unwrap();
+        assert_eq!(
+            result.generation,
+            "The tool reported an error: no such record"
+        );
+    }
+
+    #[tokio::test]
+    async fn timeouts_go_back_to_the_model() {
+        let result = executor(OneCallAgent::failing_with(timeout), true)
+            .call(prompt_args! { "input" => "find it" })
+            .await
+            .unwrap();
+        assert_eq!(result.generation, "The tool call failed: lookup timed out");
+    }
+
+    #[tokio::test]
+    async fn streamed_tool_errors_end_the_run_when_breaking_on_errors() {
+        let stream = executor(OneCallAgent::failing_with(tool_error), true)
+            .stream(prompt_args! { "input" => "find it" })
+            .await
+            .unwrap();
+        let chunks = stream.collect::<Vec<_>>().await;
+        let last = chunks.last().unwrap().as_ref().unwrap();
+        assert_eq!(last.value["choices"][0]["delta"]["is_error"], true);
+        assert_eq!(last.value["choices"][0]["finish_reason"], FINISH_STOP);
+        // The run ended at the failed call, without a model turn after it.
+        assert!(chunks.iter().all(|chunk| {
+            !is_tool_result(&chunk.as_ref().unwrap().value["choices"][0]["delta"])
+        }));
+    }
+
+    #[tokio::test]
+    async fn structured_results_are_streamed_as_parsed() {
+        let reply = StubReply::Structured {
+            text: "Found 1 record".to_string(),
+            structured: json!({ "records": [{ "id": 7 }] }),
+        };
+        let (client, server) =
+            connect(StubServer::default().with_tool(tool("lookup"), reply)).await;
+        tokio::spawn(server.waiting());
+        let lookup = RmcpTool::new(
+            "lookup".to_string(),
+            tool("lookup"),
+            Arc::new(client),
+            &RmcpToolOptions::default(),
+        );
+        let agent = OneCallAgent {
+            tool: Arc::new(lookup),
+        };
+
+        let stream = executor(agent, true)
+            .stream(prompt_args! { "input" => "find it" })
+            .await
+            .unwrap();
+        let chunks = stream.collect::<Vec<_>>().await;
+        let result = chunks
+            .iter()
+            .map(|chunk| &chunk.as_ref().unwrap().value["choices"][0]["delta"])
+            .find(|delta| is_tool_result(delta))
+            .unwrap();
+        assert_eq!(result["parsed"], json!({ "records": [{ "id": 7 }] }));
+        assert_eq!(result["is_error"], false);
+    }
+}
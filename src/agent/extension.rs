@@ -1,16 +1,58 @@
 use std::pin::Pin;
+use std::sync::Arc;
 
 use async_trait::async_trait;
 use futures_util::Stream;
 use langchain_rust::agent::{Agent, AgentError};
 use langchain_rust::chain::ChainError;
 use langchain_rust::prompt::PromptArgs;
-use langchain_rust::schemas::{AgentAction, AgentEvent};
+use langchain_rust::schemas::{AgentAction, AgentEvent, Message};
 
 use crate::agent::intermediate::IntermediateStep;
+use crate::client::elicitation::ElicitationHandle;
+use crate::client::sampling::Sampler;
+use crate::tool::native::LangchainTool;
+use crate::tool::registry::{ToolRegistry, ToolSource};
 
 #[async_trait]
 pub trait AgentExt: Agent {
+    /// Tools keyed by the name the model calls them by, with the server each
+    /// one came from. Defaults to the agent's [`Agent::get_tools`], as native
+    /// tools.
+    fn tool_registry(&self) -> ToolRegistry {
+        let mut registry = ToolRegistry::new();
+        for tool in self.get_tools() {
+            let tool = Arc::new(LangchainTool::new(tool));
+            if let Err(e) = registry.register(ToolSource::Native, tool) {
+                tracing::warn!("Skipping tool: {e}");
+            }
+        }
+        registry
+    }
+
+    /// Picks up tool list changes announced by MCP servers. Returns whether
+    /// the tools changed; called by the executor between iterations.
+    async fn refresh_tools(&self) -> Result<bool, AgentError> {
+        Ok(false)
+    }
+
+    /// System messages with the content of the pinned MCP resources, read
+    /// once at the start of each run.
+    async fn pinned_resources(&self) -> Vec<Message> {
+        Vec::new()
+    }
+
+    /// Sampler answering MCP sampling requests with the agent's model, if
+    /// sampling is enabled.
+    fn sampler(&self) -> Option<Sampler> {
+        None
+    }
+
+    /// Handle the agent's MCP servers send elicitation requests to, if any.
+    fn elicitation(&self) -> Option<ElicitationHandle> {
+        None
+    }
+
     async fn plan_with_steps(
         &self,
         intermediate_steps: &[impl IntermediateStep],
@@ -1,32 +1,155 @@
 use langchain_rust::{
     agent::AgentError,
-    schemas::{AgentAction, FunctionCallResponse, LogTools, Message},
+    schemas::{AgentAction, FunctionCallResponse, LogTools, Message, MessageType},
 };
 use serde_json::json;
 
+use crate::tool::extension::ToolOutput;
+
 pub trait IntermediateStep: Send + Sync {
+    fn action(&self) -> &AgentAction;
+
+    /// The text of the tool result.
+    fn observation(&self) -> &str;
+
     fn append_to_conversation(&self, thoughts: &mut Vec<Message>) -> Result<(), AgentError>;
 }
 
 impl IntermediateStep for (AgentAction, String) {
+    fn action(&self) -> &AgentAction {
+        &self.0
+    }
+
+    fn observation(&self) -> &str {
+        &self.1
+    }
+
     fn append_to_conversation(&self, thoughts: &mut Vec<Message>) -> Result<(), AgentError> {
         let (action, observation) = (&self.0, &self.1);
 
-        let LogTools { tool_id, tools } = match serde_json::from_str(&action.log) {
-            Ok(log_tools) => log_tools,
-            Err(e) => return Err(AgentError::SerdeJsonError(e)),
+        let tool_id = append_tool_calls(action, thoughts)?;
+        thoughts.push(Message::new_tool_message(observation, tool_id));
+
+        Ok(())
+    }
+}
+
+impl IntermediateStep for (AgentAction, ToolOutput) {
+    fn action(&self) -> &AgentAction {
+        &self.0
+    }
+
+    fn observation(&self) -> &str {
+        &self.1.content
+    }
+
+    fn append_to_conversation(&self, thoughts: &mut Vec<Message>) -> Result<(), AgentError> {
+        let (action, output) = (&self.0, &self.1);
+
+        let tool_id = append_tool_calls(action, thoughts)?;
+        // Nothing may come between tool calls and their results, so images
+        // of earlier calls in the turn move after this result.
+        let images_start = thoughts
+            .iter()
+            .rposition(|message| !is_image_message(message))
+            .map_or(0, |idx| idx + 1);
+        thoughts.insert(images_start, output.to_tool_message(tool_id));
+        thoughts.extend(output.image_message());
+
+        Ok(())
+    }
+}
+
+fn is_image_message(message: &Message) -> bool {
+    message.message_type == MessageType::HumanMessage && message.images.is_some()
+}
+
+fn append_tool_calls(
+    action: &AgentAction,
+    thoughts: &mut Vec<Message>,
+) -> Result<String, AgentError> {
+    let LogTools { tool_id, tools } = match serde_json::from_str(&action.log) {
+        Ok(log_tools) => log_tools,
+        Err(e) => return Err(AgentError::SerdeJsonError(e)),
+    };
+
+    let tools: Vec<FunctionCallResponse> = match serde_json::from_str(&tools) {
+        Ok(tools) => tools,
+        Err(e) => return Err(AgentError::SerdeJsonError(e)),
+    };
+
+    // Steps of the same turn share one assistant message. Checking the
+    // messages rather than whether there are any keeps later turns, and turns
+    // following a summary, attached to their own tool calls.
+    let announced = thoughts.iter().any(|message| {
+        message
+            .tool_calls
+            .as_ref()
+            .and_then(|calls| calls.as_array())
+            .is_some_and(|calls| calls.iter().any(|call| call["id"] == tool_id.as_str()))
+    });
+    if !announced {
+        thoughts.push(Message::new_ai_message("").with_tool_calls(json!(tools)));
+    }
+
+    Ok(tool_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tool::content::ContentPart;
+
+    use super::*;
+
+    fn step(id: &str, parts: Vec<ContentPart>) -> (AgentAction, ToolOutput) {
+        let tools = json!([
+            { "id": "call_a", "type": "function", "function": { "name": "chart", "arguments": "{}" } },
+            { "id": "call_b", "type": "function", "function": { "name": "chart", "arguments": "{}" } }
+        ]);
+        let action = AgentAction {
+            tool: "chart".to_string(),
+            tool_input: "{}".to_string(),
+            log: json!({ "tool_id": id, "tools": tools.to_string() }).to_string(),
         };
+        (action, ToolOutput::from_parts(parts))
+    }
 
-        let tools: Vec<FunctionCallResponse> = match serde_json::from_str(&tools) {
-            Ok(tools) => tools,
-            Err(e) => return Err(AgentError::SerdeJsonError(e)),
+    #[test]
+    fn images_follow_the_tool_messages_of_the_turn() {
+        let image = ContentPart::Image {
+            data: "iVBORw0KGgo=".to_string(),
+            mime_type: "image/png".to_string(),
+        };
+        let text = ContentPart::Text {
+            text: "done".to_string(),
         };
 
-        if thoughts.is_empty() {
-            thoughts.push(Message::new_ai_message("").with_tool_calls(json!(tools)));
-        }
-        thoughts.push(Message::new_tool_message(observation, tool_id));
+        let mut messages = Vec::new();
+        step("call_a", vec![image])
+            .append_to_conversation(&mut messages)
+            .unwrap();
+        step("call_b", vec![text])
+            .append_to_conversation(&mut messages)
+            .unwrap();
 
-        Ok(())
+        let types = messages
+            .iter()
+            .map(|message| message.message_type.clone())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            types,
+            [
+                MessageType::AIMessage,
+                MessageType::ToolMessage,
+                MessageType::ToolMessage,
+                MessageType::HumanMessage,
+            ]
+        );
+        assert!(messages[1].images.is_none());
+        assert_eq!(messages[1].content, "[image: image/png]");
+        assert_eq!(
+            messages[3].images.as_ref().unwrap()[0].image_url,
+            "data:image/png;base64,iVBORw0KGgo="
+        );
     }
 }
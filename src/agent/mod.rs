@@ -1,8 +1,13 @@
+pub mod approval;
 pub mod builder;
+pub mod context;
 pub mod core;
 pub mod executor;
 pub mod extension;
 pub mod intermediate;
+pub mod prompt;
+pub mod scratchpad;
+pub mod toolset;
 
 pub use builder::OpenAIMcpAgentBuilder;
 pub use core::OpenAIMcpAgent;
@@ -0,0 +1,237 @@
+use std::sync::Arc;
+
+use langchain_rust::agent::AgentError;
+use langchain_rust::schemas::Message;
+use rmcp::model::{
+    GetPromptRequestParam, JsonObject, Prompt, PromptMessageContent, PromptMessageRole,
+    ResourceContents,
+};
+
+use crate::client::McpClient;
+
+/// A prompt rendered by an MCP server through `prompts/get`.
+#[derive(Debug, Clone)]
+pub struct McpPrompt {
+    pub name: String,
+    pub description: Option<String>,
+    /// Text of the prompt messages, in order, with their role.
+    pub messages: Vec<(PromptMessageRole, String)>,
+}
+
+impl McpPrompt {
+    /// Fetches the prompt `name` from the server, rendered with `arguments`.
+    pub async fn get<C: McpClient + ?Sized>(
+        client: &C,
+        name: impl ToString,
+        arguments: Option<JsonObject>,
+    ) -> Result<Self, AgentError> {
+        let name = name.to_string();
+        let error = |e| AgentError::OtherError(format!("Failed to get prompt `{name}`: {e}"));
+        let result = client
+            .connected_peer()
+            .await
+            .map_err(error)?
+            .get_prompt(GetPromptRequestParam {
+                name: name.clone(),
+                arguments,
+            })
+            .await
+            .map_err(error)?;
+
+        let messages = result
+            .messages
+            .into_iter()
+            .filter_map(|message| {
+                let text = match message.content {
+                    PromptMessageContent::Text { text } => text,
+                    PromptMessageContent::Resource { resource } => match &resource.resource {
+                        ResourceContents::TextResourceContents { uri, text, .. } => {
+                            format!("[resource: {uri}]\n{text}")
+                        }
+                        ResourceContents::BlobResourceContents { uri, .. } => {
+                            tracing::warn!("prompt `{name}`: skipping binary resource {uri}");
+                            return None;
+                        }
+                    },
+                    _ => {
+                        tracing::warn!("prompt `{name}`: skipping non-text message");
+                        return None;
+                    }
+                };
+                Some((message.role, text))
+            })
+            .collect();
+
+        Ok(Self {
+            name,
+            description: result.description,
+            messages,
+        })
+    }
+
+    /// The prompt as an agent prefix: one human or AI message per prompt
+    /// message, in order, sent ahead of the chat history and input.
+    pub fn to_messages(&self) -> Vec<Message> {
+        self.messages
+            .iter()
+            .map(|(role, text)| match role {
+                PromptMessageRole::User => Message::new_human_message(text),
+                PromptMessageRole::Assistant => Message::new_ai_message(text),
+            })
+            .collect()
+    }
+}
+
+/// The prompts offered by an MCP server, usable as agent prompt presets.
+#[derive(Clone)]
+pub struct McpPromptLibrary {
+    client: Arc<dyn McpClient>,
+    prompts: Vec<Prompt>,
+}
+
+impl McpPromptLibrary {
+    /// Lists the prompts of the server behind `client`.
+    pub async fn load<C: McpClient>(client: Arc<C>) -> Result<Self, AgentError> {
+        let error = |e| AgentError::OtherError(format!("Failed to list prompts: {e}"));
+        let prompts = client
+            .connected_peer()
+            .await
+            .map_err(error)?
+            .list_all_prompts()
+            .await
+            .map_err(error)?;
+
+        Ok(Self { client, prompts })
+    }
+
+    /// The available presets, with their description and arguments.
+    pub fn presets(&self) -> &[Prompt] {
+        &self.prompts
+    }
+
+    pub fn preset(&self, name: &str) -> Option<&Prompt> {
+        self.prompts.iter().find(|p| p.name == name)
+    }
+
+    /// Renders the preset `name`. Fails without a request if the preset does
+    /// not exist or a required argument is missing.
+    pub async fn get(
+        &self,
+        name: &str,
+        arguments: Option<JsonObject>,
+    ) -> Result<McpPrompt, AgentError> {
+        let Some(preset) = self.preset(name) else {
+            return Err(AgentError::OtherError(format!(
+                "Unknown prompt preset `{name}`"
+            )));
+        };
+
+        let missing = preset
+            .arguments
+            .iter()
+            .flatten()
+            .filter(|arg| arg.required.unwrap_or(false))
+            .filter(|arg| {
+                !arguments
+                    .as_ref()
+                    .is_some_and(|args| args.contains_key(&arg.name))
+            })
+            .map(|arg| arg.name.as_str())
+            .collect::<Vec<_>>();
+        if !missing.is_empty() {
+            return Err(AgentError::OtherError(format!(
+                "Prompt preset `{name}` is missing required arguments: {}",
+                missing.join(", ")
+            )));
+        }
+
+        McpPrompt::get(self.client.as_ref(), name, arguments).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use langchain_rust::schemas::MessageType;
+    use rmcp::model::PromptArgument;
+    use serde_json::json;
+
+    use super::*;
+    use crate::client::testing::{StubServer, connect};
+
+    fn argument(name: &str, required: bool) -> PromptArgument {
+        PromptArgument {
+            name: name.to_string(),
+            title: None,
+            description: None,
+            required: Some(required),
+        }
+    }
+
+    /// A server offering a `review` prompt that requires a `language`.
+    fn review_server() -> StubServer {
+        StubServer {
+            prompts: vec![Prompt::new(
+                "review",
+                Some("Reviews code"),
+                Some(vec![argument("language", true), argument("focus", false)]),
+            )],
+            ..Default::default()
+        }
+    }
+
+    fn arguments(value: serde_json::Value) -> Option<JsonObject> {
+        Some(rmcp::model::object(value))
+    }
+
+    #[tokio::test]
+    async fn renders_prompts_with_their_roles() {
+        let (client, server) = connect(review_server()).await;
+        tokio::spawn(server.waiting());
+
+        let prompt = McpPrompt::get(&client, "review", arguments(json!({ "language": "rust" })))
+            .await
+            .unwrap();
+        assert_eq!(prompt.description.as_deref(), Some("Reviews code"));
+        assert_eq!(
+            prompt.messages,
+            vec![
+                (
+                    PromptMessageRole::Assistant,
+                    "You follow the `review` prompt.".to_string()
+                ),
+                (PromptMessageRole::User, "language: rust".to_string()),
+            ]
+        );
+
+        let messages = prompt.to_messages();
+        let roles = messages.iter().map(|m| &m.message_type).collect::<Vec<_>>();
+        assert_eq!(roles, [&MessageType::AIMessage, &MessageType::HumanMessage]);
+        assert_eq!(messages[1].content, "language: rust");
+    }
+
+    #[tokio::test]
+    async fn presets_require_their_arguments() {
+        let server = review_server();
+        let log = server.log.clone();
+        let (client, server) = connect(server).await;
+        tokio::spawn(server.waiting());
+        let library = McpPromptLibrary::load(Arc::new(client)).await.unwrap();
+        assert_eq!(library.presets().len(), 1);
+
+        for args in [None, arguments(json!({ "focus": "safety" }))] {
+            let error = library.get("review", args).await.unwrap_err().to_string();
+            assert!(
+                error.contains("missing required arguments: language"),
+                "{error}"
+            );
+        }
+        let error = library.get("summary", None).await.unwrap_err().to_string();
+        assert!(error.contains("Unknown prompt preset `summary`"), "{error}");
+        assert!(log.prompts().is_empty());
+
+        let args = arguments(json!({ "language": "rust", "focus": "safety" }));
+        let prompt = library.get("review", args).await.unwrap();
+        assert_eq!(prompt.messages[1].1, "focus: safety\nlanguage: rust");
+        assert_eq!(log.prompts(), ["review"]);
+    }
+}
@@ -0,0 +1,316 @@
+use std::collections::VecDeque;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use langchain_rust::agent::AgentError;
+use langchain_rust::language_models::llm::LLM;
+use langchain_rust::llm::{OpenAI, OpenAIConfig};
+use langchain_rust::schemas::Message;
+
+use crate::agent::intermediate::IntermediateStep;
+
+/// Number of summaries kept around.
+const CACHED_RUNS: usize = 16;
+/// Observations longer than this are cut in heuristic digests.
+const DIGEST_OBSERVATION_CHARS: usize = 300;
+
+pub const DEFAULT_SUMMARY_PROMPT: &str = r#"You summarize the tool calls an AI assistant made while working on a task.
+Write a concise summary that keeps every fact the assistant may still need: the values returned by tools, identifiers, file names, errors and what has already been tried.
+If a summary of earlier steps is given, return an updated summary covering both the earlier and the new steps.
+Answer with the summary only."#;
+
+/// A step of a run, as handed to a [`StepSummarizer`].
+#[derive(Debug, Clone)]
+pub struct StepRecord {
+    pub tool: String,
+    pub input: String,
+    pub observation: String,
+}
+
+/// Condenses the older steps of a long run so that the model keeps their
+/// results without seeing every message.
+#[async_trait]
+pub trait StepSummarizer: Send + Sync {
+    /// Summarizes `steps`. `previous` is the summary of the steps that came
+    /// before them in the same run, if there is one.
+    async fn summarize(
+        &self,
+        previous: Option<&str>,
+        steps: &[StepRecord],
+    ) -> Result<String, AgentError>;
+}
+
+/// Summarizes steps with an LLM.
+pub struct LlmSummarizer {
+    llm: OpenAI<OpenAIConfig>,
+    prompt: String,
+}
+
+impl LlmSummarizer {
+    pub fn new(llm: OpenAI<OpenAIConfig>) -> Self {
+        Self {
+            llm,
+            prompt: DEFAULT_SUMMARY_PROMPT.to_string(),
+        }
+    }
+
+    pub fn with_model(mut self, model: impl ToString) -> Self {
+        self.llm = self.llm.with_model(model.to_string());
+        self
+    }
+
+    /// System prompt of the summarization request, [`DEFAULT_SUMMARY_PROMPT`]
+    /// by default.
+    pub fn with_prompt(mut self, prompt: impl ToString) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+}
+
+#[async_trait]
+impl StepSummarizer for LlmSummarizer {
+    async fn summarize(
+        &self,
+        previous: Option<&str>,
+        steps: &[StepRecord],
+    ) -> Result<String, AgentError> {
+        let mut request = String::new();
+        if let Some(previous) = previous {
+            request.push_str(&format!(
+                "Summary of earlier steps:\n{previous}\n\nNew steps:\n"
+            ));
+        }
+        for (i, step) in steps.iter().enumerate() {
+            request.push_str(&format!(
+                "{}. {}({})\nResult: {}\n",
+                i + 1,
+                step.tool,
+                step.input,
+                step.observation
+            ));
+        }
+
+        let messages = vec![
+            Message::new_system_message(&self.prompt),
+            Message::new_human_message(&request),
+        ];
+        let result = self
+            .llm
+            .generate(&messages)
+            .await
+            .map_err(|e| AgentError::OtherError(format!("Failed to summarize steps: {e}")))?;
+
+        Ok(result.generation)
+    }
+}
+
+/// A summary without an LLM: one line per step with its tool, arguments and
+/// the start of its result.
+pub fn digest(steps: &[StepRecord]) -> String {
+    steps
+        .iter()
+        .enumerate()
+        .map(|(i, step)| {
+            let mut observation = step
+                .observation
+                .chars()
+                .take(DIGEST_OBSERVATION_CHARS)
+                .collect::<String>();
+            if observation.len() < step.observation.len() {
+                observation.push_str("...");
+            }
+            format!("{}. {}({}) -> {observation}", i + 1, step.tool, step.input)
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+struct CachedSummary {
+    /// Hashes of the summarized steps' logs, in order.
+    steps: Vec<u64>,
+    summary: String,
+}
+
+/// Summarizes the steps that no longer fit in the prompt.
+pub(crate) struct Scratchpad {
+    summarizer: Option<Arc<dyn StepSummarizer>>,
+    /// Recent summaries, so that each iteration of a run only summarizes the
+    /// steps that left the prompt since the previous one.
+    cache: Mutex<VecDeque<CachedSummary>>,
+}
+
+impl Scratchpad {
+    pub fn new(summarizer: Option<Arc<dyn StepSummarizer>>) -> Self {
+        Self {
+            summarizer,
+            cache: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Summarizes `steps` with the summarizer, falling back to a digest if
+    /// there is none or it fails. `steps` holds whole turns (see
+    /// `ContextManager::fit`), so no tool call sent to the model loses its
+    /// result to the summary.
+    pub async fn summarize<S: IntermediateStep>(&self, steps: &[&S]) -> String {
+        let records = steps.iter().map(|step| record(*step)).collect::<Vec<_>>();
+        let Some(summarizer) = &self.summarizer else {
+            return digest(&records);
+        };
+
+        // The log of a step holds its tool call id, which identifies it.
+        let hashes = steps
+            .iter()
+            .map(|step| {
+                let mut hasher = DefaultHasher::new();
+                step.action().log.hash(&mut hasher);
+                hasher.finish()
+            })
+            .collect::<Vec<_>>();
+
+        let cached = self
+            .cache
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|c| hashes.starts_with(&c.steps))
+            .max_by_key(|c| c.steps.len())
+            .map(|c| (c.steps.len(), c.summary.clone()));
+        let (previous, new_records) = match &cached {
+            Some((count, summary)) if *count == records.len() => return summary.clone(),
+            Some((count, summary)) => (Some(summary.as_str()), &records[*count..]),
+            None => (None, &records[..]),
+        };
+
+        match summarizer.summarize(previous, new_records).await {
+            Ok(summary) => {
+                let mut cache = self.cache.lock().unwrap();
+                cache.retain(|c| !hashes.starts_with(&c.steps));
+                if cache.len() >= CACHED_RUNS {
+                    cache.pop_front();
+                }
+                cache.push_back(CachedSummary {
+                    steps: hashes,
+                    summary: summary.clone(),
+                });
+                summary
+            }
+            Err(e) => {
+                tracing::warn!("Summarizer failed, using a digest of the steps instead: {e}");
+                digest(&records)
+            }
+        }
+    }
+}
+
+fn record(step: &impl IntermediateStep) -> StepRecord {
+    let action = step.action();
+    StepRecord {
+        tool: action.tool.clone(),
+        input: action.tool_input.clone(),
+        observation: step.observation().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use langchain_rust::schemas::AgentAction;
+
+    use super::*;
+
+    /// Records what it was asked to summarize.
+    #[derive(Default)]
+    struct RecordingSummarizer {
+        calls: Mutex<Vec<(Option<String>, usize)>>,
+        fail: bool,
+    }
+
+    #[async_trait]
+    impl StepSummarizer for RecordingSummarizer {
+        async fn summarize(
+            &self,
+            previous: Option<&str>,
+            steps: &[StepRecord],
+        ) -> Result<String, AgentError> {
+            let mut calls = self.calls.lock().unwrap();
+            calls.push((previous.map(str::to_string), steps.len()));
+            if self.fail {
+                return Err(AgentError::OtherError("unavailable".to_string()));
+            }
+            Ok(format!("summary {}", calls.len()))
+        }
+    }
+
+    fn step(id: &str, observation: &str) -> (AgentAction, String) {
+        let action = AgentAction {
+            tool: "search".to_string(),
+            tool_input: format!("{{\"q\":\"{id}\"}}"),
+            log: format!("{{\"tool_id\":\"{id}\",\"tools\":\"[]\"}}"),
+        };
+        (action, observation.to_string())
+    }
+
+    #[test]
+    fn digest_lists_steps_and_cuts_long_results() {
+        let records = vec![
+            StepRecord {
+                tool: "search".to_string(),
+                input: "{}".to_string(),
+                observation: "found".to_string(),
+            },
+            StepRecord {
+                tool: "read".to_string(),
+                input: "{}".to_string(),
+                observation: "é".repeat(DIGEST_OBSERVATION_CHARS + 1),
+            },
+        ];
+
+        let digest = digest(&records);
+        let lines = digest.lines().collect::<Vec<_>>();
+
+        assert_eq!(lines[0], "1. search({}) -> found");
+        let cut = format!(
+            "2. read({{}}) -> {}...",
+            "é".repeat(DIGEST_OBSERVATION_CHARS)
+        );
+        assert_eq!(lines[1], cut);
+    }
+
+    #[tokio::test]
+    async fn summaries_are_cached_and_extended() {
+        let summarizer = Arc::new(RecordingSummarizer::default());
+        let scratchpad = Scratchpad::new(Some(summarizer.clone()));
+        let (a, b, c) = (step("a", "1"), step("b", "2"), step("c", "3"));
+
+        assert_eq!(scratchpad.summarize(&[&a, &b]).await, "summary 1");
+        // The same steps again are not summarized twice.
+        assert_eq!(scratchpad.summarize(&[&a, &b]).await, "summary 1");
+        // New steps are summarized on top of the cached summary.
+        assert_eq!(scratchpad.summarize(&[&a, &b, &c]).await, "summary 2");
+        // Steps that do not extend a cached run start over.
+        assert_eq!(scratchpad.summarize(&[&b, &c]).await, "summary 3");
+
+        assert_eq!(
+            *summarizer.calls.lock().unwrap(),
+            vec![(None, 2), (Some("summary 1".to_string()), 1), (None, 2)]
+        );
+    }
+
+    #[tokio::test]
+    async fn failed_summaries_fall_back_to_a_digest() {
+        let summarizer = Arc::new(RecordingSummarizer {
+            fail: true,
+            ..Default::default()
+        });
+        let scratchpad = Scratchpad::new(Some(summarizer.clone()));
+        let a = step("a", "1");
+
+        assert_eq!(
+            scratchpad.summarize(&[&a]).await,
+            "1. search({\"q\":\"a\"}) -> 1"
+        );
+        // Nothing was cached, so the summarizer is asked again.
+        scratchpad.summarize(&[&a]).await;
+        assert_eq!(summarizer.calls.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,331 @@
+use std::sync::Arc;
+
+use langchain_rust::agent::AgentError;
+use langchain_rust::schemas::Message;
+use tokio::sync::watch;
+
+use crate::client::McpClient;
+use crate::tool::extension::ToolExt;
+use crate::tool::filter::{DroppedTool, ToolFilter};
+use crate::tool::native::describe;
+use crate::tool::registry::{ToolRegistry, ToolSource};
+use crate::tool::resource::{
+    LIST_RESOURCES_TOOL, McpResourceTool, READ_RESOURCE_TOOL, ResourceToolKind, read_resource,
+};
+use crate::tool::rmcp::{RmcpTool, RmcpToolOptions};
+
+/// Tools listed from one MCP client.
+pub(crate) struct McpToolSource {
+    pub namespace: Option<String>,
+    pub client: Arc<dyn McpClient>,
+    pub tools: Vec<rmcp::model::Tool>,
+    tools_changed: Option<watch::Receiver<u64>>,
+    /// Set while a re-list has not succeeded yet.
+    relist_pending: bool,
+}
+
+impl McpToolSource {
+    pub fn new(
+        namespace: Option<String>,
+        client: Arc<dyn McpClient>,
+        tools: Vec<rmcp::model::Tool>,
+    ) -> Self {
+        let mut tools_changed = client.tool_list_changed();
+        // Only notifications received from now on make the list stale.
+        if let Some(rx) = &mut tools_changed {
+            rx.mark_unchanged();
+        }
+
+        Self {
+            namespace,
+            client,
+            tools,
+            tools_changed,
+            relist_pending: false,
+        }
+    }
+
+    fn supports_resources(&self) -> bool {
+        // Assume support when the server info is not known.
+        self.client
+            .peer()
+            .peer_info()
+            .is_none_or(|info| info.capabilities.resources.is_some())
+    }
+
+    fn is_stale(&self) -> bool {
+        self.relist_pending
+            || self
+                .tools_changed
+                .as_ref()
+                .is_some_and(|rx| rx.has_changed().unwrap_or(false))
+    }
+
+    async fn relist(&mut self) -> Result<(), AgentError> {
+        if let Some(rx) = &mut self.tools_changed {
+            rx.mark_unchanged();
+        }
+        self.relist_pending = true;
+        let peer = self
+            .client
+            .connected_peer()
+            .await
+            .map_err(|e| AgentError::ToolError(format!("Failed to re-list MCP tools: {e}")))?;
+        self.tools = peer
+            .list_all_tools()
+            .await
+            .map_err(|e| AgentError::ToolError(format!("Failed to re-list MCP tools: {e}")))?;
+        self.relist_pending = false;
+        Ok(())
+    }
+}
+
+/// Everything needed to (re)build an agent's tool registry.
+pub(crate) struct AgentToolset {
+    pub native_tools: Vec<Arc<dyn ToolExt>>,
+    pub mcp_sources: Vec<McpToolSource>,
+    pub namespace_separator: String,
+    pub tool_filter: ToolFilter,
+    pub rmcp_options: RmcpToolOptions,
+    /// Adds `list_resources` and `read_resource` tools for every namespaced
+    /// server that has resources, and one pair shared by the others.
+    pub expose_resources: bool,
+    /// Resource URIs read into the system prompt at the start of each run.
+    pub pinned_resources: Vec<String>,
+    /// Set when tools were re-listed but the registry was not rebuilt from
+    /// them yet.
+    pub rebuild_pending: bool,
+}
+
+impl AgentToolset {
+    fn exposed_name(&self, namespace: Option<&str>, name: &str) -> String {
+        ToolRegistry::exposed_name(namespace, &self.namespace_separator, name)
+    }
+
+    pub fn build_registry(&self) -> Result<ToolRegistry, AgentError> {
+        let mut registry = ToolRegistry::new();
+        for tool in &self.native_tools {
+            let name = ToolRegistry::sanitize_name(&tool.name());
+            if let Some(reason) = self.tool_filter.check(&name, &describe(tool.as_ref())) {
+                registry.record_dropped(DroppedTool {
+                    name,
+                    server: None,
+                    reason,
+                });
+                continue;
+            }
+            registry.register(ToolSource::Native, tool.clone())?;
+        }
+        if self.expose_resources {
+            // Un-namespaced servers share one pair of tools, whose names
+            // would collide otherwise.
+            let shared = self
+                .mcp_sources
+                .iter()
+                .filter(|source| source.namespace.is_none() && source.supports_resources())
+                .map(|source| source.client.clone())
+                .collect::<Vec<_>>();
+            if !shared.is_empty() {
+                self.register_resource_tools(&mut registry, None, shared)?;
+            }
+        }
+        for source in &self.mcp_sources {
+            if self.expose_resources && source.namespace.is_some() && source.supports_resources() {
+                self.register_resource_tools(
+                    &mut registry,
+                    source.namespace.as_deref(),
+                    vec![source.client.clone()],
+                )?;
+            }
+
+            for tool in &source.tools {
+                let name = self.exposed_name(source.namespace.as_deref(), &tool.name);
+                if let Some(reason) = self.tool_filter.check(&name, tool) {
+                    registry.record_dropped(DroppedTool {
+                        name,
+                        server: source.namespace.clone(),
+                        reason,
+                    });
+                    continue;
+                }
+                let tool_source = ToolSource::Mcp {
+                    server: source.namespace.clone(),
+                    tool_name: tool.name.to_string(),
+                };
+                let t = RmcpTool::new(
+                    name,
+                    tool.clone(),
+                    source.client.clone(),
+                    &self.rmcp_options,
+                );
+                registry.register(tool_source, Arc::new(t))?;
+            }
+        }
+        Ok(registry)
+    }
+
+    fn register_resource_tools(
+        &self,
+        registry: &mut ToolRegistry,
+        namespace: Option<&str>,
+        clients: Vec<Arc<dyn McpClient>>,
+    ) -> Result<(), AgentError> {
+        for (name, kind) in [
+            (LIST_RESOURCES_TOOL, ResourceToolKind::List),
+            (READ_RESOURCE_TOOL, ResourceToolKind::Read),
+        ] {
+            let name = self.exposed_name(namespace, name);
+            let tool = McpResourceTool::new(name.clone(), kind, clients.clone());
+            if let Some(reason) = self.tool_filter.check(&name, &describe(&tool)) {
+                registry.record_dropped(DroppedTool {
+                    name,
+                    server: namespace.map(str::to_string),
+                    reason,
+                });
+                continue;
+            }
+            let tool_source = ToolSource::McpResources {
+                server: namespace.map(str::to_string),
+            };
+            registry.register(tool_source, Arc::new(tool))?;
+        }
+        Ok(())
+    }
+
+    /// Reads the pinned resources into system messages. Each URI is looked up
+    /// on the servers in registration order; unreadable ones are skipped.
+    pub async fn read_pinned_resources(&self) -> Vec<Message> {
+        let mut messages = Vec::with_capacity(self.pinned_resources.len());
+        for uri in &self.pinned_resources {
+            let mut found = false;
+            for source in self.mcp_sources.iter().filter(|s| s.supports_resources()) {
+                match read_resource(source.client.as_ref(), uri).await {
+                    Ok(output) => {
+                        let text = format!("Content of resource `{uri}`:\n{}", output.content);
+                        messages.push(Message::new_system_message(&text));
+                        found = true;
+                        break;
+                    }
+                    Err(e) => tracing::debug!("resource `{uri}` not read from server: {e}"),
+                }
+            }
+            if !found {
+                tracing::warn!("Pinned resource `{uri}` could not be read from any MCP server");
+            }
+        }
+        messages
+    }
+
+    /// Re-lists the tools of every server that reported a change since the
+    /// last call. Returns whether the registry needs to be rebuilt, which
+    /// stays true until [`Self::mark_rebuilt`] is called.
+    pub async fn refresh(&mut self) -> Result<bool, AgentError> {
+        for source in &mut self.mcp_sources {
+            if source.is_stale() {
+                tracing::info!(
+                    "Tool list of {} changed, re-listing",
+                    source.namespace.as_deref().unwrap_or("MCP server")
+                );
+                self.rebuild_pending = true;
+                source.relist().await?;
+            }
+        }
+        Ok(self.rebuild_pending)
+    }
+
+    pub fn mark_rebuilt(&mut self) {
+        self.rebuild_pending = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::testing::{DroppedClient, StubServer, connect};
+    use crate::tool::extension::ToolCallContext;
+    use crate::tool::registry::DEFAULT_NAMESPACE_SEPARATOR;
+
+    fn toolset(mcp_sources: Vec<McpToolSource>) -> AgentToolset {
+        AgentToolset {
+            native_tools: Vec::new(),
+            mcp_sources,
+            namespace_separator: DEFAULT_NAMESPACE_SEPARATOR.to_string(),
+            tool_filter: ToolFilter::default(),
+            rmcp_options: RmcpToolOptions::default(),
+            expose_resources: false,
+            pinned_resources: Vec::new(),
+            rebuild_pending: false,
+        }
+    }
+
+    async fn source(namespace: Option<&str>, tools: &[&str]) -> McpToolSource {
+        let server = StubServer::with_tools(tools);
+        let tools = server.tools.clone();
+        let (client, server) = connect(server).await;
+        // The session ends when the server side is dropped.
+        tokio::spawn(server.waiting());
+        McpToolSource::new(namespace.map(str::to_string), Arc::new(client), tools)
+    }
+
+    fn names(registry: &ToolRegistry) -> Vec<&str> {
+        registry.iter().map(|entry| entry.name.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn reports_collisions_between_servers() {
+        let toolset = toolset(vec![
+            source(None, &["search"]).await,
+            source(None, &["search"]).await,
+        ]);
+
+        let error = toolset.build_registry().err().unwrap().to_string();
+        assert!(
+            error.contains("tool name `search` is exposed by both"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn namespaces_keep_tools_of_different_servers_apart() {
+        let toolset = toolset(vec![
+            source(Some("github"), &["search"]).await,
+            source(None, &["search"]).await,
+        ]);
+
+        let registry = toolset.build_registry().unwrap();
+        assert_eq!(names(&registry), ["github__search", "search"]);
+        assert_eq!(
+            registry.get("github__search").unwrap().source,
+            ToolSource::Mcp {
+                server: Some("github".to_string()),
+                tool_name: "search".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn sanitized_names_call_the_original_tool() {
+        let toolset = toolset(vec![source(Some("fs"), &["files/read.v2"]).await]);
+
+        let registry = toolset.build_registry().unwrap();
+        assert_eq!(names(&registry), ["fs__files_read_v2"]);
+
+        let tool = registry.get("fs__files_read_v2").unwrap().tool.clone();
+        let output = tool
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap();
+        assert_eq!(output.content, "files/read.v2");
+    }
+
+    #[tokio::test]
+    async fn relisting_reconnects_closed_clients() {
+        let client = Arc::new(DroppedClient::new(StubServer::with_tools(&["search"])).await);
+        let mut source = McpToolSource::new(None, client.clone(), Vec::new());
+
+        source.relist().await.unwrap();
+        assert_eq!(client.reconnects(), 1);
+        assert_eq!(source.tools[0].name, "search");
+        assert!(!source.is_stale());
+    }
+}
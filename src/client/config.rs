@@ -0,0 +1,262 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use langchain_rust::agent::AgentError;
+use serde::Deserialize;
+
+use crate::client::connection::{McpServerSpec, StdioServerSpec};
+
+/// The `mcpServers` config format used by desktop MCP clients:
+///
+/// ```json
+/// {
+///   "mcpServers": {
+///     "github": {
+///       "command": "npx",
+///       "args": ["-y", "@modelcontextprotocol/server-github"],
+///       "env": { "GITHUB_TOKEN": "${GITHUB_TOKEN}" }
+///     },
+///     "search": { "url": "https://search.example.com/mcp", "disabled": true }
+///   }
+/// }
+/// ```
+///
+/// `${VAR}` and `${VAR:-default}` in commands, arguments, env values, working
+/// directories and URLs are replaced with environment variables when a
+/// server is started.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct McpServersConfig {
+    #[serde(rename = "mcpServers", default)]
+    pub servers: BTreeMap<String, McpServerConfig>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct McpServerConfig {
+    pub command: Option<String>,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+    pub cwd: Option<String>,
+    pub url: Option<String>,
+    /// Transport of `url` servers: `sse`, or `http` / `streamable-http`.
+    /// Without it, URLs ending in `/sse` use SSE and the others streamable
+    /// HTTP.
+    #[serde(rename = "type")]
+    pub transport: Option<String>,
+    #[serde(default)]
+    pub disabled: bool,
+    pub enabled: Option<bool>,
+}
+
+impl McpServersConfig {
+    pub fn from_json(json: &str) -> Result<Self, AgentError> {
+        serde_json::from_str(json)
+            .map_err(|e| AgentError::OtherError(format!("Invalid mcpServers config: {e}")))
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, AgentError> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path).map_err(|e| {
+            AgentError::OtherError(format!("Failed to read {}: {e}", path.display()))
+        })?;
+        Self::from_json(&json)
+    }
+
+    /// The servers that are not disabled, by name.
+    pub fn enabled_servers(&self) -> impl Iterator<Item = (&String, &McpServerConfig)> {
+        self.servers
+            .iter()
+            .filter(|(_, server)| server.is_enabled())
+    }
+}
+
+impl McpServerConfig {
+    pub fn is_enabled(&self) -> bool {
+        !self.disabled && self.enabled.unwrap_or(true)
+    }
+
+    /// The connection spec of the server, with environment variables
+    /// interpolated.
+    pub fn to_spec(&self) -> Result<McpServerSpec, AgentError> {
+        match (&self.command, &self.url) {
+            (Some(command), None) => {
+                let mut spec = StdioServerSpec::new(interpolate(command)?);
+                for arg in &self.args {
+                    spec = spec.arg(interpolate(arg)?);
+                }
+                for (key, value) in &self.env {
+                    spec = spec.env(key, interpolate(value)?);
+                }
+                if let Some(cwd) = &self.cwd {
+                    spec = spec.cwd(interpolate(cwd)?);
+                }
+                Ok(spec.into())
+            }
+            (None, Some(url)) => {
+                let url = interpolate(url)?;
+                match self.transport.as_deref() {
+                    Some("sse") => Ok(McpServerSpec::sse(url)),
+                    Some("http" | "streamable-http" | "streamableHttp") => {
+                        Ok(McpServerSpec::streamable_http(url))
+                    }
+                    Some(other) => Err(AgentError::OtherError(format!(
+                        "Unsupported MCP transport `{other}`"
+                    ))),
+                    None if url.trim_end_matches('/').ends_with("/sse") => {
+                        Ok(McpServerSpec::sse(url))
+                    }
+                    None => Ok(McpServerSpec::streamable_http(url)),
+                }
+            }
+            (Some(_), Some(_)) => Err(AgentError::OtherError(
+                "An MCP server needs either `command` or `url`, not both".to_string(),
+            )),
+            (None, None) => Err(AgentError::OtherError(
+                "An MCP server needs a `command` or a `url`".to_string(),
+            )),
+        }
+    }
+}
+
+/// Replaces `${VAR}` and `${VAR:-default}` with environment variables.
+/// Unset variables without a default are an error.
+fn interpolate(value: &str) -> Result<String, AgentError> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        let Some(len) = rest[start + 2..].find('}') else {
+            return Err(AgentError::OtherError(format!(
+                "Unterminated `${{` in `{value}`"
+            )));
+        };
+        let expr = &rest[start + 2..start + 2 + len];
+        let (name, default) = match expr.split_once(":-") {
+            Some((name, default)) => (name, Some(default)),
+            None => (expr, None),
+        };
+        // As in the shell, `:-` also replaces variables set to "".
+        let var = std::env::var(name)
+            .ok()
+            .filter(|var| !var.is_empty() || default.is_none())
+            .or_else(|| default.map(str::to_string));
+        match var {
+            Some(var) => result.push_str(&var),
+            None => {
+                return Err(AgentError::OtherError(format!(
+                    "Environment variable `{name}` is not set"
+                )));
+            }
+        }
+        rest = &rest[start + 3 + len..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Sets a variable only these tests read, so they can run in parallel.
+    fn set_var(name: &str, value: &str) {
+        // SAFETY: no other test reads or writes these variables.
+        unsafe { std::env::set_var(name, value) };
+    }
+
+    fn url_server(url: &str, transport: Option<&str>) -> McpServerConfig {
+        McpServerConfig {
+            url: Some(url.to_string()),
+            transport: transport.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn interpolates_variables() {
+        set_var("RMCP_AGENT_TEST_HOST", "example.com");
+        assert_eq!(
+            interpolate("https://${RMCP_AGENT_TEST_HOST}/mcp").unwrap(),
+            "https://example.com/mcp"
+        );
+        assert_eq!(interpolate("no variables").unwrap(), "no variables");
+        assert!(interpolate("${RMCP_AGENT_TEST_UNSET}").is_err());
+        assert!(interpolate("${RMCP_AGENT_TEST_HOST").is_err());
+    }
+
+    #[test]
+    fn interpolates_defaults() {
+        set_var("RMCP_AGENT_TEST_PORT", "9000");
+        set_var("RMCP_AGENT_TEST_EMPTY", "");
+        assert_eq!(
+            interpolate("${RMCP_AGENT_TEST_PORT:-8080}").unwrap(),
+            "9000"
+        );
+        assert_eq!(
+            interpolate("${RMCP_AGENT_TEST_UNSET:-8080}").unwrap(),
+            "8080"
+        );
+        assert_eq!(interpolate("${RMCP_AGENT_TEST_UNSET:-}").unwrap(), "");
+        // Empty values are replaced by the default, but kept without one.
+        assert_eq!(
+            interpolate("${RMCP_AGENT_TEST_EMPTY:-8080}").unwrap(),
+            "8080"
+        );
+        assert_eq!(interpolate("a${RMCP_AGENT_TEST_EMPTY}b").unwrap(), "ab");
+    }
+
+    #[test]
+    fn infers_url_transports() {
+        let spec = |url, transport| url_server(url, transport).to_spec().unwrap();
+        assert!(matches!(
+            spec("https://a.example/sse", None),
+            McpServerSpec::Sse { .. }
+        ));
+        assert!(matches!(
+            spec("https://a.example/sse/", None),
+            McpServerSpec::Sse { .. }
+        ));
+        assert!(matches!(
+            spec("https://a.example/mcp", None),
+            McpServerSpec::StreamableHttp { .. }
+        ));
+        assert!(matches!(
+            spec("https://a.example/sse", Some("http")),
+            McpServerSpec::StreamableHttp { .. }
+        ));
+        assert!(matches!(
+            spec("https://a.example/mcp", Some("sse")),
+            McpServerSpec::Sse { .. }
+        ));
+        assert!(
+            url_server("https://a.example/mcp", Some("ws"))
+                .to_spec()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn builds_stdio_specs() {
+        set_var("RMCP_AGENT_TEST_TOKEN", "secret");
+        let server = McpServerConfig {
+            command: Some("npx".to_string()),
+            args: vec!["-y".to_string(), "server".to_string()],
+            env: HashMap::from([("TOKEN".to_string(), "${RMCP_AGENT_TEST_TOKEN}".to_string())]),
+            ..Default::default()
+        };
+        let McpServerSpec::Stdio(spec) = server.to_spec().unwrap() else {
+            panic!("expected a stdio server");
+        };
+        assert_eq!(spec.command, "npx");
+        assert_eq!(spec.args, ["-y", "server"]);
+        assert_eq!(spec.env["TOKEN"], "secret");
+
+        let both = McpServerConfig {
+            url: Some("https://a.example/mcp".to_string()),
+            ..server
+        };
+        assert!(both.to_spec().is_err());
+        assert!(McpServerConfig::default().to_spec().is_err());
+    }
+}
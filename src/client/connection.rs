@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use langchain_rust::agent::AgentError;
+use rmcp::service::RunningService;
+use rmcp::transport::{SseClientTransport, StreamableHttpClientTransport, TokioChildProcess};
+use rmcp::{RoleClient, ServiceExt};
+use tokio::process::Command;
+
+use crate::client::handler::AgentClientHandler;
+
+/// How to reach an MCP server.
+#[derive(Debug, Clone)]
+pub enum McpServerSpec {
+    /// A child process speaking MCP over stdin/stdout.
+    Stdio(StdioServerSpec),
+    StreamableHttp {
+        url: String,
+    },
+    Sse {
+        url: String,
+    },
+}
+
+impl McpServerSpec {
+    pub fn stdio(command: impl ToString) -> StdioServerSpec {
+        StdioServerSpec::new(command)
+    }
+
+    pub fn streamable_http(url: impl ToString) -> Self {
+        McpServerSpec::StreamableHttp {
+            url: url.to_string(),
+        }
+    }
+
+    pub fn sse(url: impl ToString) -> Self {
+        McpServerSpec::Sse {
+            url: url.to_string(),
+        }
+    }
+}
+
+/// Command line of a stdio MCP server.
+#[derive(Debug, Clone, Default)]
+pub struct StdioServerSpec {
+    pub command: String,
+    pub args: Vec<String>,
+    /// Added to the environment inherited from this process.
+    pub env: HashMap<String, String>,
+    pub cwd: Option<PathBuf>,
+}
+
+impl StdioServerSpec {
+    pub fn new(command: impl ToString) -> Self {
+        Self {
+            command: command.to_string(),
+            ..Default::default()
+        }
+    }
+
+    pub fn arg(mut self, arg: impl ToString) -> Self {
+        self.args.push(arg.to_string());
+        self
+    }
+
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: ToString,
+    {
+        self.args.extend(args.into_iter().map(|a| a.to_string()));
+        self
+    }
+
+    pub fn env(mut self, key: impl ToString, value: impl ToString) -> Self {
+        self.env.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    pub fn cwd(mut self, cwd: impl Into<PathBuf>) -> Self {
+        self.cwd = Some(cwd.into());
+        self
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.command);
+        command.args(&self.args).envs(&self.env);
+        if let Some(cwd) = &self.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+}
+
+impl From<StdioServerSpec> for McpServerSpec {
+    fn from(spec: StdioServerSpec) -> Self {
+        McpServerSpec::Stdio(spec)
+    }
+}
+
+/// An initialized MCP client together with the tools its server offers.
+pub struct McpConnection {
+    pub client: Arc<RunningService<RoleClient, AgentClientHandler>>,
+    pub tools: Vec<rmcp::model::Tool>,
+}
+
+impl McpConnection {
+    /// Starts the transport described by `spec`, completes the MCP
+    /// handshake with `handler` and lists all the server's tools.
+    pub async fn connect(
+        spec: &McpServerSpec,
+        handler: AgentClientHandler,
+    ) -> Result<Self, AgentError> {
+        let client = match spec {
+            McpServerSpec::Stdio(stdio) => {
+                let transport = TokioChildProcess::new(stdio.command()).map_err(|e| {
+                    AgentError::OtherError(format!(
+                        "Failed to start MCP server `{}`: {e}",
+                        stdio.command
+                    ))
+                })?;
+                handler.serve(transport).await.map_err(|e| {
+                    AgentError::OtherError(format!(
+                        "MCP server `{}` failed to initialize: {e}",
+                        stdio.command
+                    ))
+                })?
+            }
+            McpServerSpec::StreamableHttp { url } => {
+                // The transport only connects with the first request.
+                let transport = StreamableHttpClientTransport::from_uri(url.as_str());
+                handler.serve(transport).await.map_err(|e| {
+                    AgentError::OtherError(format!("Failed to connect to `{url}`: {e}"))
+                })?
+            }
+            McpServerSpec::Sse { url } => {
+                let transport = SseClientTransport::start(url.as_str()).await.map_err(|e| {
+                    AgentError::OtherError(format!("Failed to connect to `{url}`: {e}"))
+                })?;
+                handler.serve(transport).await.map_err(|e| {
+                    AgentError::OtherError(format!(
+                        "MCP server at `{url}` failed to initialize: {e}"
+                    ))
+                })?
+            }
+        };
+
+        let tools = client
+            .list_all_tools()
+            .await
+            .map_err(|e| AgentError::ToolError(format!("Failed to list MCP tools: {e}")))?;
+
+        Ok(Self {
+            client: Arc::new(client),
+            tools,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::CallToolRequestParam;
+    use rmcp::transport::streamable_http_server::StreamableHttpService;
+    use rmcp::transport::streamable_http_server::session::local::LocalSessionManager;
+
+    use super::*;
+    use crate::client::testing::StubServer;
+
+    /// Serves `server` over streamable HTTP on a free local port, until the
+    /// test ends.
+    async fn serve_http(server: StubServer) -> String {
+        let service = StreamableHttpService::new(
+            move || Ok(server.clone()),
+            Arc::new(LocalSessionManager::default()),
+            Default::default(),
+        );
+        let router = axum::Router::new().nest_service("/mcp", service);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        url
+    }
+
+    async fn assert_round_trip(spec: McpServerSpec) {
+        let connection = McpConnection::connect(&spec, AgentClientHandler::default())
+            .await
+            .unwrap();
+        let names = connection
+            .tools
+            .iter()
+            .map(|tool| tool.name.as_ref())
+            .collect::<Vec<_>>();
+        assert_eq!(names, ["search", "read"]);
+
+        let result = connection
+            .client
+            .call_tool(CallToolRequestParam {
+                name: "read".into(),
+                arguments: None,
+            })
+            .await
+            .unwrap();
+        assert_eq!(result.content[0].as_text().unwrap().text, "read");
+    }
+
+    #[tokio::test]
+    async fn connects_over_streamable_http() {
+        let url = serve_http(StubServer::with_tools(&["search", "read"])).await;
+        assert_round_trip(McpServerSpec::streamable_http(url)).await;
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn connects_over_stdio() {
+        let server = StubServer::with_tools(&["search", "read"]);
+        let listener = crate::client::testing::StubListener::start(server).await;
+        assert_round_trip(listener.spec()).await;
+    }
+
+    #[tokio::test]
+    async fn reports_servers_that_cannot_be_started() {
+        let spec = McpServerSpec::stdio("rmcp-agent-missing-server").into();
+        let error = McpConnection::connect(&spec, AgentClientHandler::default())
+            .await
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            error.contains("Failed to start MCP server `rmcp-agent-missing-server`"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn reports_servers_that_cannot_be_reached() {
+        // A port nothing listens on.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/mcp", listener.local_addr().unwrap());
+        drop(listener);
+
+        let spec = McpServerSpec::streamable_http(&url);
+        let error = McpConnection::connect(&spec, AgentClientHandler::default())
+            .await
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            error.contains(&format!("Failed to connect to `{url}`")),
+            "{error}"
+        );
+    }
+}
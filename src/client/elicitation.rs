@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::sync::{Arc, Mutex};
+
+use rmcp::ErrorData as McpError;
+use rmcp::model::{
+    CreateElicitationRequestParam, CreateElicitationResult, ElicitationAction, JsonObject,
+};
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::tool::schema::SchemaValidator;
+
+/// A server asking the user for input in the middle of a tool call.
+#[derive(Debug, Clone, Serialize)]
+pub struct ElicitationRequest {
+    /// Id to pass back to the [`ElicitationHandle`].
+    pub id: String,
+    pub message: String,
+    /// Flat object schema of the values the server asks for.
+    pub requested_schema: JsonObject,
+}
+
+#[derive(Default)]
+struct ElicitationRuns {
+    next_id: u64,
+    /// Request sinks of the streaming runs in progress.
+    active: HashMap<u64, UnboundedSender<ElicitationRequest>>,
+}
+
+/// Answers `elicitation/create` requests from MCP servers with input
+/// supplied by the user.
+///
+/// Give it to [`OpenAIMcpAgentBuilder::elicitation`], which hands it to the
+/// servers the builder connects and to the agent's executors. For clients
+/// connected elsewhere, give the same handle to
+/// [`AgentClientHandler::with_elicitation`] and to
+/// [`OpenAIMcpAgentExecutor::with_elicitation`]. Requests are delivered on
+/// the receiver returned by [`ElicitationHandle::new`] and, for streaming
+/// runs, as `elicitation_required` deltas on the stream. The server waits
+/// until the request is answered with [`Self::respond`].
+///
+/// [`OpenAIMcpAgentBuilder::elicitation`]: crate::agent::builder::OpenAIMcpAgentBuilder::elicitation
+/// [`AgentClientHandler::with_elicitation`]: crate::client::handler::AgentClientHandler::with_elicitation
+/// [`OpenAIMcpAgentExecutor::with_elicitation`]: crate::agent::executor::OpenAIMcpAgentExecutor::with_elicitation
+#[derive(Clone)]
+pub struct ElicitationHandle {
+    pending: Arc<Mutex<HashMap<String, oneshot::Sender<CreateElicitationResult>>>>,
+    requests: UnboundedSender<ElicitationRequest>,
+    runs: Arc<Mutex<ElicitationRuns>>,
+}
+
+impl ElicitationHandle {
+    pub fn new() -> (Self, UnboundedReceiver<ElicitationRequest>) {
+        let (requests, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handle = Self {
+            pending: Arc::new(Mutex::new(HashMap::new())),
+            requests,
+            runs: Arc::new(Mutex::new(ElicitationRuns::default())),
+        };
+        (handle, rx)
+    }
+
+    /// Resolves the pending request `id`. Returns false if there is no such
+    /// request, e.g. because it was already answered.
+    pub fn respond(&self, id: &str, response: CreateElicitationResult) -> bool {
+        let sender = self.pending.lock().unwrap().remove(id);
+        match sender {
+            Some(sender) => sender.send(response).is_ok(),
+            None => false,
+        }
+    }
+
+    pub fn accept(&self, id: &str, content: Value) -> bool {
+        self.respond(
+            id,
+            CreateElicitationResult {
+                action: ElicitationAction::Accept,
+                content: Some(content),
+            },
+        )
+    }
+
+    pub fn decline(&self, id: &str) -> bool {
+        self.respond(id, without_content(ElicitationAction::Decline))
+    }
+
+    pub fn cancel(&self, id: &str) -> bool {
+        self.respond(id, without_content(ElicitationAction::Cancel))
+    }
+
+    /// Ids of the requests still waiting for a response.
+    pub fn pending(&self) -> Vec<String> {
+        self.pending.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// Starts a streaming run that gets the requests made until the returned
+    /// guard is dropped. Servers do not say which run a request belongs to,
+    /// so every run in progress gets it. `forwarder`, the task passing the
+    /// requests on, is stopped with the run.
+    pub(crate) fn begin_run(
+        &self,
+        requests: UnboundedSender<ElicitationRequest>,
+        forwarder: Option<JoinHandle<()>>,
+    ) -> ElicitationRun {
+        let mut runs = self.runs.lock().unwrap();
+        let id = runs.next_id;
+        runs.next_id += 1;
+        runs.active.insert(id, requests);
+        ElicitationRun {
+            handle: self.clone(),
+            id,
+            forwarder,
+        }
+    }
+
+    /// Stops sending requests to the run. Once the last run has ended and
+    /// the handle's receiver is dropped, pending requests are cancelled, as
+    /// nobody is left to answer them.
+    fn end_run(&self, run: u64) {
+        let mut runs = self.runs.lock().unwrap();
+        runs.active.remove(&run);
+        if runs.active.is_empty() && self.requests.is_closed() {
+            self.pending.lock().unwrap().clear();
+        }
+    }
+
+    /// Asks the user for the values described by the request's schema and
+    /// waits for the response. Accepted content that does not match the
+    /// schema is refused, as the server would reject it anyway.
+    pub async fn elicit(
+        &self,
+        params: CreateElicitationRequestParam,
+    ) -> Result<CreateElicitationResult, McpError> {
+        let validator = SchemaValidator::compile(&params.requested_schema);
+        let request = ElicitationRequest {
+            id: Uuid::now_v7().to_string(),
+            message: params.message,
+            requested_schema: params.requested_schema,
+        };
+
+        let (tx, rx) = oneshot::channel();
+        self.pending.lock().unwrap().insert(request.id.clone(), tx);
+        // Withdraws the request if the server stops waiting for it.
+        let _withdraw = WithdrawOnDrop {
+            pending: &self.pending,
+            id: request.id.clone(),
+        };
+        let mut delivered = false;
+        for run in self.runs.lock().unwrap().active.values() {
+            delivered |= run.send(request.clone()).is_ok();
+        }
+        delivered |= self.requests.send(request).is_ok();
+        if !delivered {
+            return Ok(without_content(ElicitationAction::Cancel));
+        }
+
+        let response = rx
+            .await
+            .unwrap_or_else(|_| without_content(ElicitationAction::Cancel));
+
+        if let (Some(validator), Some(content)) = (&validator, &response.content)
+            && response.action == ElicitationAction::Accept
+        {
+            validator.validate(content).map_err(|e| {
+                McpError::invalid_params(
+                    format!("elicitation response does not match the requested schema: {e}"),
+                    None,
+                )
+            })?;
+        }
+        Ok(response)
+    }
+}
+
+fn without_content(action: ElicitationAction) -> CreateElicitationResult {
+    CreateElicitationResult {
+        action,
+        content: None,
+    }
+}
+
+struct WithdrawOnDrop<'a> {
+    pending: &'a Mutex<HashMap<String, oneshot::Sender<CreateElicitationResult>>>,
+    id: String,
+}
+
+impl Drop for WithdrawOnDrop<'_> {
+    fn drop(&mut self) {
+        self.pending.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Ends a streaming run of an [`ElicitationHandle`] when dropped, however
+/// the run finishes.
+pub(crate) struct ElicitationRun {
+    handle: ElicitationHandle,
+    id: u64,
+    forwarder: Option<JoinHandle<()>>,
+}
+
+impl Drop for ElicitationRun {
+    fn drop(&mut self) {
+        self.handle.end_run(self.id);
+        if let Some(forwarder) = &self.forwarder {
+            forwarder.abort();
+        }
+    }
+}
+
+impl Debug for ElicitationHandle {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ElicitationHandle")
+            .field("pending", &self.pending())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn params() -> CreateElicitationRequestParam {
+        CreateElicitationRequestParam {
+            message: "Who are you?".to_string(),
+            requested_schema: json!({
+                "type": "object",
+                "properties": { "name": { "type": "string" } },
+                "required": ["name"]
+            })
+            .as_object()
+            .unwrap()
+            .clone(),
+        }
+    }
+
+    #[tokio::test]
+    async fn requests_reach_the_receiver_and_runs_in_progress() {
+        let (handle, mut requests) = ElicitationHandle::new();
+        let (run_tx, mut run_rx) = unbounded_channel();
+        let run = handle.begin_run(run_tx, None);
+
+        let elicit = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.elicit(params()).await }
+        });
+
+        let request = requests.recv().await.unwrap();
+        assert_eq!(request.message, "Who are you?");
+        assert_eq!(run_rx.recv().await.unwrap().id, request.id);
+
+        assert!(handle.accept(&request.id, json!({ "name": "Ada" })));
+        assert!(!handle.decline(&request.id));
+        let response = elicit.await.unwrap().unwrap();
+        assert_eq!(response.action, ElicitationAction::Accept);
+        assert_eq!(response.content, Some(json!({ "name": "Ada" })));
+
+        drop(run);
+        assert!(handle.pending().is_empty());
+    }
+
+    #[tokio::test]
+    async fn content_not_matching_the_schema_is_refused() {
+        let (handle, mut requests) = ElicitationHandle::new();
+        let elicit = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.elicit(params()).await }
+        });
+
+        let request = requests.recv().await.unwrap();
+        handle.accept(&request.id, json!({ "name": 7 }));
+        assert!(elicit.await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn unanswerable_requests_are_cancelled() {
+        let (handle, requests) = ElicitationHandle::new();
+        drop(requests);
+        assert_eq!(
+            handle.elicit(params()).await.unwrap(),
+            without_content(ElicitationAction::Cancel)
+        );
+
+        // Requests pending when the last run ends are cancelled too.
+        let (run_tx, mut run_rx) = unbounded_channel();
+        let run = handle.begin_run(run_tx, None);
+        let elicit = tokio::spawn({
+            let handle = handle.clone();
+            async move { handle.elicit(params()).await }
+        });
+        run_rx.recv().await.unwrap();
+        drop(run);
+        assert_eq!(
+            elicit.await.unwrap().unwrap(),
+            without_content(ElicitationAction::Cancel)
+        );
+        assert!(handle.pending().is_empty());
+    }
+}
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+
+use rmcp::handler::client::progress::ProgressDispatcher;
+use rmcp::model::{
+    ClientInfo, CreateElicitationRequestParam, CreateElicitationResult, CreateMessageRequestParam,
+    CreateMessageResult, ElicitationCapability, ProgressNotificationParam,
+};
+use rmcp::service::{NotificationContext, RequestContext};
+use rmcp::{ClientHandler, ErrorData as McpError, RoleClient};
+use tokio::sync::watch;
+
+use crate::client::elicitation::ElicitationHandle;
+use crate::client::sampling::Sampler;
+
+/// Client handler for MCP connections used by an agent.
+///
+/// Unlike serving a bare [`ClientInfo`], it routes server notifications back
+/// to the tool calls that caused them (e.g. progress updates) and lets
+/// agents know when the server's tool list changes.
+#[derive(Debug, Clone)]
+pub struct AgentClientHandler {
+    info: ClientInfo,
+    pub(crate) progress: ProgressDispatcher,
+    /// Bumped on every `notifications/tools/list_changed`.
+    pub(crate) tools_changed: Arc<watch::Sender<u64>>,
+    sampler: Option<Sampler>,
+    elicitation: Option<ElicitationHandle>,
+}
+
+impl AgentClientHandler {
+    pub fn new(info: ClientInfo) -> Self {
+        Self {
+            info,
+            progress: ProgressDispatcher::new(),
+            tools_changed: Arc::new(watch::Sender::new(0)),
+            sampler: None,
+            elicitation: None,
+        }
+    }
+
+    /// Answers `sampling/createMessage` requests with `sampler`, and
+    /// advertises the sampling capability to servers.
+    pub fn with_sampler(mut self, sampler: Sampler) -> Self {
+        self.sampler = Some(sampler);
+        self
+    }
+
+    /// Passes `elicitation/create` requests on to `handle`, and advertises
+    /// the elicitation capability to servers.
+    pub fn with_elicitation(mut self, handle: ElicitationHandle) -> Self {
+        self.elicitation = Some(handle);
+        self
+    }
+}
+
+impl Default for AgentClientHandler {
+    fn default() -> Self {
+        Self::new(ClientInfo::default())
+    }
+}
+
+impl ClientHandler for AgentClientHandler {
+    async fn on_progress(
+        &self,
+        params: ProgressNotificationParam,
+        _context: NotificationContext<RoleClient>,
+    ) {
+        self.progress.handle_notification(params).await;
+    }
+
+    async fn on_tool_list_changed(&self, _context: NotificationContext<RoleClient>) {
+        tracing::debug!("server tool list changed");
+        self.tools_changed.send_modify(|version| *version += 1);
+    }
+
+    async fn create_message(
+        &self,
+        params: CreateMessageRequestParam,
+        context: RequestContext<RoleClient>,
+    ) -> Result<CreateMessageResult, McpError> {
+        match &self.sampler {
+            Some(sampler) => sampler.create_message(params, &context.meta).await,
+            None => Err(McpError::method_not_found::<
+                rmcp::model::CreateMessageRequestMethod,
+            >()),
+        }
+    }
+
+    async fn create_elicitation(
+        &self,
+        params: CreateElicitationRequestParam,
+        _context: RequestContext<RoleClient>,
+    ) -> Result<CreateElicitationResult, McpError> {
+        match &self.elicitation {
+            Some(handle) => handle.elicit(params).await,
+            None => Err(McpError::method_not_found::<
+                rmcp::model::ElicitationCreateRequestMethod,
+            >()),
+        }
+    }
+
+    fn get_info(&self) -> ClientInfo {
+        let mut info = self.info.clone();
+        if self.sampler.is_some() {
+            info.capabilities
+                .sampling
+                .get_or_insert_with(Default::default);
+        }
+        if self.elicitation.is_some() {
+            info.capabilities.elicitation = Some(ElicitationCapability {
+                schema_validation: Some(true),
+            });
+        }
+        info
+    }
+}
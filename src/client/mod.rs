@@ -0,0 +1,77 @@
+pub mod config;
+pub mod connection;
+pub mod elicitation;
+pub mod handler;
+pub mod reconnect;
+pub mod sampling;
+#[cfg(test)]
+pub(crate) mod testing;
+
+use async_trait::async_trait;
+use rmcp::handler::client::progress::ProgressDispatcher;
+use rmcp::model::InitializeRequestParam;
+use rmcp::service::{Peer, RunningService};
+use rmcp::{RoleClient, ServiceError};
+use tokio::sync::watch;
+
+use crate::client::handler::AgentClientHandler;
+
+/// A connected MCP client that tools can send requests through.
+#[async_trait]
+pub trait McpClient: Send + Sync + 'static {
+    /// The current session's peer. Send requests through
+    /// [`Self::connected_peer`] instead, which reconnects if needed.
+    fn peer(&self) -> Peer<RoleClient>;
+
+    /// Dispatcher for `notifications/progress`, if the client handler routes
+    /// them. Without one, tool calls don't report progress.
+    fn progress_dispatcher(&self) -> Option<&ProgressDispatcher> {
+        None
+    }
+
+    /// Changes whenever the server sends `notifications/tools/list_changed`.
+    /// Without it, the tools of this client are listed only once.
+    fn tool_list_changed(&self) -> Option<watch::Receiver<u64>> {
+        None
+    }
+
+    /// Starts a new session after the transport closed. Returns whether the
+    /// client can be used again; clients that cannot reconnect return false.
+    async fn reconnect(&self) -> bool {
+        false
+    }
+
+    /// The peer to send a request through, after reconnecting if the
+    /// transport closed.
+    async fn connected_peer(&self) -> Result<Peer<RoleClient>, ServiceError> {
+        let peer = self.peer();
+        if !peer.is_transport_closed() {
+            return Ok(peer);
+        }
+        if self.reconnect().await {
+            Ok(self.peer())
+        } else {
+            Err(ServiceError::TransportClosed)
+        }
+    }
+}
+
+impl McpClient for RunningService<RoleClient, InitializeRequestParam> {
+    fn peer(&self) -> Peer<RoleClient> {
+        RunningService::peer(self).clone()
+    }
+}
+
+impl McpClient for RunningService<RoleClient, AgentClientHandler> {
+    fn peer(&self) -> Peer<RoleClient> {
+        RunningService::peer(self).clone()
+    }
+
+    fn progress_dispatcher(&self) -> Option<&ProgressDispatcher> {
+        Some(&self.service().progress)
+    }
+
+    fn tool_list_changed(&self) -> Option<watch::Receiver<u64>> {
+        Some(self.service().tools_changed.subscribe())
+    }
+}
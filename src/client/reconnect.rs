@@ -0,0 +1,228 @@
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use langchain_rust::agent::AgentError;
+use rmcp::RoleClient;
+use rmcp::handler::client::progress::ProgressDispatcher;
+use rmcp::service::{Peer, RunningService};
+use tokio::sync::{Mutex, watch};
+
+use crate::client::McpClient;
+use crate::client::connection::{McpConnection, McpServerSpec};
+use crate::client::handler::AgentClientHandler;
+use crate::tool::retry::RetryPolicy;
+
+/// An MCP client that starts a new session when its transport closes.
+///
+/// Requests sent through [`McpClient::connected_peer`], as every tool call,
+/// resource read, prompt request and tool re-list is, check the connection
+/// first and ask it to reconnect if it dropped, so they keep working after a
+/// server restart or a network failure. Reconnection attempts are spaced by
+/// the backoff of a [`RetryPolicy`]. If the server comes back with a different tool list,
+/// the change is reported like a `notifications/tools/list_changed`, so
+/// agents re-list the tools before their next iteration.
+pub struct ReconnectingClient {
+    spec: McpServerSpec,
+    /// Cloned into every session, so progress routing and tool list change
+    /// subscriptions survive reconnects.
+    handler: AgentClientHandler,
+    backoff: RetryPolicy,
+    service: RwLock<Arc<RunningService<RoleClient, AgentClientHandler>>>,
+    tools: RwLock<Vec<rmcp::model::Tool>>,
+    reconnecting: Mutex<()>,
+}
+
+impl ReconnectingClient {
+    /// Connects to the server described by `spec`, failing if the first
+    /// connection cannot be established.
+    pub async fn connect(
+        spec: impl Into<McpServerSpec>,
+        handler: AgentClientHandler,
+    ) -> Result<Self, AgentError> {
+        let spec = spec.into();
+        let connection = McpConnection::connect(&spec, handler.clone()).await?;
+
+        Ok(Self {
+            spec,
+            handler,
+            backoff: RetryPolicy::new(5).with_backoff(
+                Duration::from_millis(500),
+                Duration::from_secs(30),
+                2.0,
+            ),
+            service: RwLock::new(connection.client),
+            tools: RwLock::new(connection.tools),
+            reconnecting: Mutex::new(()),
+        })
+    }
+
+    /// Number of reconnection attempts and the delay between them, 5
+    /// attempts from 500ms to 30s by default.
+    pub fn with_backoff(mut self, policy: RetryPolicy) -> Self {
+        self.backoff = policy;
+        self
+    }
+
+    /// The tools listed by the current session.
+    pub fn tools(&self) -> Vec<rmcp::model::Tool> {
+        self.tools.read().unwrap().clone()
+    }
+
+    async fn try_reconnect(&self) -> bool {
+        // Concurrent callers wait for a single reconnection.
+        let _guard = self.reconnecting.lock().await;
+        if !self.peer().is_transport_closed() {
+            return true;
+        }
+
+        for attempt in 1..=self.backoff.max_attempts {
+            let error = match McpConnection::connect(&self.spec, self.handler.clone()).await {
+                Ok(connection) => {
+                    tracing::info!("Reconnected to MCP server after {attempt} attempt(s)");
+                    let changed = {
+                        let tools = self.tools.read().unwrap();
+                        serde_json::to_value(&*tools).ok()
+                            != serde_json::to_value(&connection.tools).ok()
+                    };
+                    *self.service.write().unwrap() = connection.client;
+                    *self.tools.write().unwrap() = connection.tools;
+                    if changed {
+                        tracing::warn!("MCP server tools changed while reconnecting");
+                        self.handler
+                            .tools_changed
+                            .send_modify(|version| *version += 1);
+                    }
+                    return true;
+                }
+                Err(e) => e.to_string(),
+            };
+
+            if attempt < self.backoff.max_attempts {
+                let backoff = self.backoff.backoff(attempt);
+                tracing::warn!(
+                    "reconnect attempt {attempt}/{} failed, retrying in {backoff:?}: {error}",
+                    self.backoff.max_attempts
+                );
+                tokio::time::sleep(backoff).await;
+            } else {
+                tracing::error!("Giving up reconnecting to MCP server: {error}");
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl McpClient for ReconnectingClient {
+    fn peer(&self) -> Peer<RoleClient> {
+        self.service.read().unwrap().peer().clone()
+    }
+
+    fn progress_dispatcher(&self) -> Option<&ProgressDispatcher> {
+        Some(&self.handler.progress)
+    }
+
+    fn tool_list_changed(&self) -> Option<watch::Receiver<u64>> {
+        Some(self.handler.tools_changed.subscribe())
+    }
+
+    async fn reconnect(&self) -> bool {
+        self.try_reconnect().await
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::time::Instant;
+
+    use rmcp::ServiceError;
+    use rmcp::model::CallToolRequestParam;
+
+    use super::*;
+    use crate::client::testing::{StubListener, StubServer};
+
+    async fn client(listener: &StubListener) -> ReconnectingClient {
+        ReconnectingClient::connect(listener.spec(), AgentClientHandler::default())
+            .await
+            .unwrap()
+            .with_backoff(
+                RetryPolicy::new(3)
+                    .with_backoff(Duration::from_millis(50), Duration::from_millis(50), 1.0)
+                    .with_jitter(0.0),
+            )
+    }
+
+    /// Waits until the client notices that its transport closed.
+    async fn closed(client: &ReconnectingClient) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !client.peer().is_transport_closed() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the transport did not close");
+    }
+
+    async fn call(client: &ReconnectingClient, name: &str) -> Result<String, ServiceError> {
+        let result = client
+            .connected_peer()
+            .await?
+            .call_tool(CallToolRequestParam {
+                name: name.to_string().into(),
+                arguments: None,
+            })
+            .await?;
+        Ok(result.content[0].as_text().unwrap().text.clone())
+    }
+
+    fn names(tools: &[rmcp::model::Tool]) -> Vec<&str> {
+        tools.iter().map(|tool| tool.name.as_ref()).collect()
+    }
+
+    #[tokio::test]
+    async fn starts_a_new_session_after_the_transport_closes() {
+        let listener = StubListener::start(StubServer::with_tools(&["search"])).await;
+        let client = client(&listener).await;
+        let changes = client.tool_list_changed().unwrap();
+
+        listener.drop_sessions();
+        closed(&client).await;
+        assert_eq!(call(&client, "search").await.unwrap(), "search");
+        assert_eq!(listener.connections(), 2);
+        // The new session was initialized, and lists the same tools.
+        assert!(client.peer().peer_info().is_some());
+        assert!(!changes.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn reports_tools_that_changed_while_reconnecting() {
+        let listener = StubListener::start(StubServer::with_tools(&["search"])).await;
+        let client = client(&listener).await;
+        let mut changes = client.tool_list_changed().unwrap();
+
+        listener.set_server(StubServer::with_tools(&["search", "read"]));
+        listener.drop_sessions();
+        closed(&client).await;
+        assert_eq!(call(&client, "read").await.unwrap(), "read");
+        assert!(changes.has_changed().unwrap());
+        assert_eq!(*changes.borrow_and_update(), 1);
+        assert_eq!(names(&client.tools()), ["search", "read"]);
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_the_attempt_limit() {
+        let listener = StubListener::start(StubServer::with_tools(&["search"])).await;
+        let client = client(&listener).await;
+
+        listener.stop();
+        closed(&client).await;
+
+        // Three attempts, with a backoff between each.
+        let started = Instant::now();
+        let error = call(&client, "search").await.unwrap_err();
+        assert!(matches!(error, ServiceError::TransportClosed), "{error}");
+        assert!(started.elapsed() >= Duration::from_millis(100));
+        assert_eq!(listener.connections(), 1);
+    }
+}
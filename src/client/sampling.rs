@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, RwLock};
+
+use langchain_rust::language_models::llm::LLM;
+use langchain_rust::language_models::options::CallOptions;
+use langchain_rust::llm::{OpenAI, OpenAIConfig};
+use langchain_rust::schemas::Message;
+use rmcp::ErrorData as McpError;
+use rmcp::model::{
+    Content, CreateMessageRequestParam, CreateMessageResult, Meta, ProgressToken, RawContent, Role,
+    SamplingMessage,
+};
+use serde::Serialize;
+use tokio::sync::mpsc::UnboundedSender;
+
+/// Limits applied to `sampling/createMessage` requests from MCP servers.
+#[derive(Debug, Clone)]
+pub struct SamplingPolicy {
+    /// Upper bound for `maxTokens`; larger requests are clamped.
+    pub max_tokens: u32,
+    /// Models a server may pick through its model preferences, besides the
+    /// agent's own model. Hints naming other models are ignored.
+    pub allowed_models: Vec<String>,
+    /// Requests beyond this count in a single run are refused. See
+    /// [`Sampler`] for how requests are attributed to runs.
+    pub max_calls_per_run: u32,
+}
+
+impl Default for SamplingPolicy {
+    fn default() -> Self {
+        Self {
+            max_tokens: 1024,
+            allowed_models: Vec::new(),
+            max_calls_per_run: 5,
+        }
+    }
+}
+
+impl SamplingPolicy {
+    pub fn with_max_tokens(mut self, max_tokens: u32) -> Self {
+        self.max_tokens = max_tokens;
+        self
+    }
+
+    pub fn with_allowed_models(mut self, models: Vec<String>) -> Self {
+        self.allowed_models = models;
+        self
+    }
+
+    pub fn with_max_calls_per_run(mut self, max_calls: u32) -> Self {
+        self.max_calls_per_run = max_calls;
+        self
+    }
+}
+
+/// A sampling request answered (or refused) on behalf of a server.
+#[derive(Debug, Clone, Serialize)]
+pub struct SamplingEvent {
+    pub model: String,
+    pub max_tokens: u32,
+    pub system_prompt: Option<String>,
+    /// Text of the last message of the request.
+    pub prompt: String,
+    pub completion: Option<String>,
+    pub error: Option<String>,
+}
+
+struct SamplingLlm {
+    llm: OpenAI<OpenAIConfig>,
+    model: String,
+    policy: SamplingPolicy,
+}
+
+/// Identifies a run started with [`Sampler::begin_run`].
+pub(crate) type RunId = u64;
+
+struct ActiveRun {
+    events: Option<UnboundedSender<SamplingEvent>>,
+    calls: u32,
+}
+
+#[derive(Default)]
+struct SamplingRuns {
+    next_id: RunId,
+    active: HashMap<RunId, ActiveRun>,
+    /// Progress tokens of the tool calls in flight, by run.
+    calls_in_flight: HashMap<ProgressToken, RunId>,
+}
+
+#[derive(Default)]
+struct SamplerInner {
+    llm: RwLock<Option<SamplingLlm>>,
+    runs: Mutex<SamplingRuns>,
+}
+
+/// Answers `sampling/createMessage` with the agent's LLM.
+///
+/// Give the same sampler to [`AgentClientHandler::with_sampler`] and to
+/// [`OpenAIMcpAgentBuilder::sampling`]: the handler is created before the
+/// agent, which supplies the model when it is built.
+///
+/// Each run has its own call count. A request counts against the run whose
+/// tool call it was made for when the server copies the call's
+/// `progressToken` into the request's `_meta`. Otherwise, it counts against
+/// the only run in progress or, when runs overlap, against each of them.
+/// Requests made while no run is in progress are refused.
+///
+/// [`AgentClientHandler::with_sampler`]: crate::client::handler::AgentClientHandler::with_sampler
+/// [`OpenAIMcpAgentBuilder::sampling`]: crate::agent::builder::OpenAIMcpAgentBuilder::sampling
+#[derive(Clone, Default)]
+pub struct Sampler {
+    inner: Arc<SamplerInner>,
+}
+
+impl std::fmt::Debug for Sampler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sampler")
+            .field("attached", &self.inner.llm.read().unwrap().is_some())
+            .field("runs", &self.inner.runs.lock().unwrap().active.len())
+            .finish()
+    }
+}
+
+impl Sampler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(crate) fn attach(&self, llm: OpenAI<OpenAIConfig>, model: String, policy: SamplingPolicy) {
+        *self.inner.llm.write().unwrap() = Some(SamplingLlm { llm, model, policy });
+    }
+
+    /// Starts a run whose events go to `events` until [`Self::end_run`].
+    pub(crate) fn begin_run(&self, events: Option<UnboundedSender<SamplingEvent>>) -> RunId {
+        let mut runs = self.inner.runs.lock().unwrap();
+        let id = runs.next_id;
+        runs.next_id += 1;
+        runs.active.insert(id, ActiveRun { events, calls: 0 });
+        id
+    }
+
+    /// Stops routing events to the run and counting requests against it.
+    pub(crate) fn end_run(&self, run: RunId) {
+        let mut runs = self.inner.runs.lock().unwrap();
+        runs.active.remove(&run);
+        runs.calls_in_flight.retain(|_, call_run| *call_run != run);
+    }
+
+    /// The runs a request with `meta` is made for.
+    fn runs_of(&self, meta: &Meta) -> Vec<RunId> {
+        let runs = self.inner.runs.lock().unwrap();
+        let tracked = meta
+            .get_progress_token()
+            .and_then(|token| runs.calls_in_flight.get(&token).copied());
+        match tracked {
+            Some(run) => vec![run],
+            None => runs.active.keys().copied().collect(),
+        }
+    }
+
+    /// Counts a request against each of `runs`, unless one of them has
+    /// reached the limit of `max_calls`.
+    fn count_call(&self, runs: &[RunId], max_calls: u32) -> Result<(), String> {
+        let mut guard = self.inner.runs.lock().unwrap();
+        if runs.is_empty() {
+            return Err("sampling is only available while the agent is running".to_string());
+        }
+        let limit_reached = runs.iter().any(|run| {
+            guard
+                .active
+                .get(run)
+                .is_some_and(|run| run.calls >= max_calls)
+        });
+        if limit_reached {
+            return Err(format!(
+                "sampling limit of {max_calls} requests per run reached"
+            ));
+        }
+        for run in runs {
+            if let Some(run) = guard.active.get_mut(run) {
+                run.calls += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn emit(&self, runs: &[RunId], event: SamplingEvent) {
+        let guard = self.inner.runs.lock().unwrap();
+        for run in runs {
+            if let Some(tx) = guard.active.get(run).and_then(|run| run.events.as_ref()) {
+                let _ = tx.send(event.clone());
+            }
+        }
+    }
+
+    /// Answers a request whose `_meta` is `meta`, see [`Sampler`] for how it
+    /// is counted.
+    pub async fn create_message(
+        &self,
+        params: CreateMessageRequestParam,
+        meta: &Meta,
+    ) -> Result<CreateMessageResult, McpError> {
+        let runs = self.runs_of(meta);
+        let (mut llm, model, max_tokens) = {
+            let guard = self.inner.llm.read().unwrap();
+            let Some(sampling) = guard.as_ref() else {
+                return Err(McpError::internal_error(
+                    "sampling is not available: no agent is attached to this client",
+                    None,
+                ));
+            };
+
+            let model = params
+                .model_preferences
+                .as_ref()
+                .and_then(|prefs| prefs.hints.as_ref())
+                .into_iter()
+                .flatten()
+                .filter_map(|hint| hint.name.as_ref())
+                .find(|name| sampling.policy.allowed_models.contains(name))
+                .cloned()
+                .unwrap_or_else(|| sampling.model.clone());
+            let max_tokens = params.max_tokens.min(sampling.policy.max_tokens);
+            let max_calls = sampling.policy.max_calls_per_run;
+
+            if let Err(error) = self.count_call(&runs, max_calls) {
+                self.emit(
+                    &runs,
+                    SamplingEvent {
+                        model,
+                        max_tokens,
+                        system_prompt: params.system_prompt.clone(),
+                        prompt: last_message_text(&params.messages),
+                        completion: None,
+                        error: Some(error.clone()),
+                    },
+                );
+                return Err(McpError::invalid_request(error, None));
+            }
+
+            (sampling.llm.clone().with_model(&model), model, max_tokens)
+        };
+
+        let mut options = CallOptions::new().with_max_tokens(max_tokens);
+        if let Some(temperature) = params.temperature {
+            options = options.with_temperature(temperature);
+        }
+        if let Some(stop) = params.stop_sequences.clone() {
+            options = options.with_stop_words(stop);
+        }
+        llm.add_options(options);
+
+        let mut messages = Vec::with_capacity(params.messages.len() + 1);
+        if let Some(system_prompt) = &params.system_prompt {
+            messages.push(Message::new_system_message(system_prompt));
+        }
+        for message in &params.messages {
+            let text = content_text(&message.content);
+            messages.push(match message.role {
+                Role::User => Message::new_human_message(&text),
+                Role::Assistant => Message::new_ai_message(&text),
+            });
+        }
+
+        let mut event = SamplingEvent {
+            model: model.clone(),
+            max_tokens,
+            system_prompt: params.system_prompt.clone(),
+            prompt: last_message_text(&params.messages),
+            completion: None,
+            error: None,
+        };
+
+        match llm.generate(&messages).await {
+            Ok(result) => {
+                event.completion = Some(result.generation.clone());
+                self.emit(&runs, event);
+                Ok(CreateMessageResult {
+                    model,
+                    stop_reason: Some("endTurn".to_string()),
+                    message: SamplingMessage {
+                        role: Role::Assistant,
+                        content: Content::text(result.generation),
+                    },
+                })
+            }
+            Err(e) => {
+                event.error = Some(e.to_string());
+                self.emit(&runs, event);
+                Err(McpError::internal_error(
+                    format!("sampling failed: {e}"),
+                    None,
+                ))
+            }
+        }
+    }
+}
+
+/// The run a tool call belongs to, so that sampling requests made for the
+/// call count against that run.
+#[derive(Debug, Clone)]
+pub(crate) struct SamplingScope {
+    sampler: Sampler,
+    run: RunId,
+}
+
+impl SamplingScope {
+    pub(crate) fn new(sampler: Sampler, run: RunId) -> Self {
+        Self { sampler, run }
+    }
+
+    /// Attributes requests carrying `token` to the run until the returned
+    /// guard is dropped.
+    pub(crate) fn track(&self, token: ProgressToken) -> TrackedCall {
+        let mut runs = self.sampler.inner.runs.lock().unwrap();
+        if runs.active.contains_key(&self.run) {
+            runs.calls_in_flight.insert(token.clone(), self.run);
+        }
+        TrackedCall {
+            sampler: self.sampler.clone(),
+            token,
+        }
+    }
+}
+
+/// A tool call in flight, see [`SamplingScope::track`].
+pub(crate) struct TrackedCall {
+    sampler: Sampler,
+    token: ProgressToken,
+}
+
+impl Drop for TrackedCall {
+    fn drop(&mut self) {
+        let mut runs = self.sampler.inner.runs.lock().unwrap();
+        runs.calls_in_flight.remove(&self.token);
+    }
+}
+
+fn content_text(content: &Content) -> String {
+    match &content.raw {
+        RawContent::Text(text) => text.text.clone(),
+        RawContent::Image(image) => format!("[image: {}]", image.mime_type),
+        RawContent::Audio(audio) => format!("[audio: {}]", audio.mime_type),
+        RawContent::Resource(_) => "[resource]".to_string(),
+        RawContent::ResourceLink(link) => format!("[resource link: {}]", link.uri),
+    }
+}
+
+fn last_message_text(messages: &[SamplingMessage]) -> String {
+    messages
+        .last()
+        .map(|m| content_text(&m.content))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::NumberOrString;
+    use tokio::sync::mpsc::unbounded_channel;
+
+    use super::*;
+
+    fn event() -> SamplingEvent {
+        SamplingEvent {
+            model: "gpt-4o".to_string(),
+            max_tokens: 16,
+            system_prompt: None,
+            prompt: "hi".to_string(),
+            completion: Some("hello".to_string()),
+            error: None,
+        }
+    }
+
+    fn meta(token: &str) -> Meta {
+        let mut meta = Meta::new();
+        meta.set_progress_token(progress_token(token));
+        meta
+    }
+
+    fn progress_token(token: &str) -> ProgressToken {
+        ProgressToken(NumberOrString::String(token.to_string().into()))
+    }
+
+    #[test]
+    fn overlapping_runs_are_counted_apart() {
+        let sampler = Sampler::new();
+        let first = sampler.begin_run(None);
+        let second = sampler.begin_run(None);
+        let _first_call = SamplingScope::new(sampler.clone(), first).track(progress_token("a"));
+        let _second_call = SamplingScope::new(sampler.clone(), second).track(progress_token("b"));
+
+        for _ in 0..2 {
+            let runs = sampler.runs_of(&meta("a"));
+            assert_eq!(runs, [first]);
+            assert!(sampler.count_call(&runs, 2).is_ok());
+        }
+        assert!(sampler.count_call(&sampler.runs_of(&meta("a")), 2).is_err());
+
+        // The first run using up its requests leaves the second one alone.
+        let runs = sampler.runs_of(&meta("b"));
+        assert_eq!(runs, [second]);
+        assert!(sampler.count_call(&runs, 2).is_ok());
+
+        // A request that cannot be attributed counts against both runs, and
+        // is refused since the first one has no requests left.
+        let mut runs = sampler.runs_of(&Meta::new());
+        runs.sort();
+        assert_eq!(runs, [first, second]);
+        assert!(sampler.count_call(&runs, 2).is_err());
+
+        // Once the first run ends, requests go to the second one.
+        sampler.end_run(first);
+        assert_eq!(sampler.runs_of(&meta("a")), [second]);
+        assert!(sampler.count_call(&[second], 2).is_ok());
+        assert!(sampler.count_call(&[second], 2).is_err());
+    }
+
+    #[test]
+    fn requests_outside_of_runs_are_refused() {
+        let sampler = Sampler::new();
+        assert!(sampler.runs_of(&meta("a")).is_empty());
+        assert!(sampler.count_call(&[], 2).is_err());
+
+        // Earlier requests do not count against a later run.
+        let run = sampler.begin_run(None);
+        assert!(sampler.count_call(&[run], 1).is_ok());
+        sampler.end_run(run);
+        let run = sampler.begin_run(None);
+        assert!(sampler.count_call(&[run], 1).is_ok());
+    }
+
+    #[test]
+    fn finished_calls_are_no_longer_tracked() {
+        let sampler = Sampler::new();
+        let first = sampler.begin_run(None);
+        let second = sampler.begin_run(None);
+        let call = SamplingScope::new(sampler.clone(), first).track(progress_token("a"));
+        assert_eq!(sampler.runs_of(&meta("a")), [first]);
+
+        drop(call);
+        assert_eq!(sampler.runs_of(&meta("a")).len(), 2);
+        sampler.end_run(first);
+        assert_eq!(sampler.runs_of(&meta("a")), [second]);
+    }
+
+    #[test]
+    fn events_reach_the_runs_of_the_request() {
+        let sampler = Sampler::new();
+        let (first_tx, mut first_rx) = unbounded_channel();
+        let (second_tx, mut second_rx) = unbounded_channel();
+
+        let first = sampler.begin_run(Some(first_tx));
+        let second = sampler.begin_run(Some(second_tx));
+        sampler.emit(&[first, second], event());
+        assert!(first_rx.try_recv().is_ok());
+        assert!(second_rx.try_recv().is_ok());
+
+        sampler.emit(&[second], event());
+        assert!(first_rx.try_recv().is_err());
+        assert!(second_rx.try_recv().is_ok());
+
+        // Ended runs get no events.
+        sampler.end_run(second);
+        sampler.emit(&[second], event());
+        assert!(second_rx.try_recv().is_err());
+    }
+}
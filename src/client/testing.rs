@@ -0,0 +1,454 @@
+//! In-process MCP servers for tests.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use rmcp::model::{
+    AnnotateAble, CallToolRequestParam, CallToolResult, ClientResult, Content,
+    CreateElicitationRequestParam, GetPromptRequestParam, GetPromptResult, JsonObject,
+    ListPromptsResult, ListResourcesResult, ListToolsResult, PaginatedRequestParam,
+    ProgressNotificationParam, ProgressToken, Prompt, PromptMessage, PromptMessageRole,
+    RawResource, ReadResourceRequestParam, ReadResourceResult, Request, ResourceContents,
+    ServerCapabilities, ServerInfo, ServerRequest, Tool, object,
+};
+use rmcp::service::{Peer, RequestContext, RunningService, RunningServiceCancellationToken};
+use rmcp::{ErrorData as McpError, RoleClient, RoleServer, ServerHandler, ServiceExt};
+use serde_json::{Value, json};
+
+use crate::client::McpClient;
+use crate::client::connection::McpServerSpec;
+use crate::client::handler::AgentClientHandler;
+
+/// How a [`StubServer`] answers calls to one of its tools.
+#[derive(Clone)]
+pub(crate) enum StubReply {
+    /// The name of the tool, as text.
+    Echo,
+    /// `text` as text content and `structured` as structured content.
+    Structured { text: String, structured: Value },
+    /// Nothing, until the client cancels the request.
+    Hang,
+    /// A JSON-RPC error.
+    Fail,
+    /// `steps` progress notifications, then the name of the tool.
+    Progress { steps: u32 },
+    /// Asks the client for a `name`, then answers with the content the user
+    /// accepted, or with the action they took.
+    Elicit,
+}
+
+/// A `tools/call` request received by a [`StubServer`].
+#[derive(Debug, Clone)]
+pub(crate) struct StubCall {
+    pub name: String,
+    pub arguments: Option<JsonObject>,
+    pub progress_token: Option<ProgressToken>,
+}
+
+/// What a [`StubServer`] and its clones received.
+#[derive(Default)]
+pub(crate) struct StubLog {
+    calls: Mutex<Vec<StubCall>>,
+    prompts: Mutex<Vec<String>>,
+    cancelled: AtomicUsize,
+}
+
+impl StubLog {
+    pub fn calls(&self) -> Vec<StubCall> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    /// Names of the prompts requested with `prompts/get`.
+    pub fn prompts(&self) -> Vec<String> {
+        self.prompts.lock().unwrap().clone()
+    }
+
+    /// Waits until `count` requests were cancelled by the client.
+    pub async fn cancelled(&self, count: usize) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while self.cancelled.load(Ordering::SeqCst) < count {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the request was not cancelled");
+    }
+}
+
+/// A server listing `tools`, `resources` and `prompts`. Tool calls are
+/// answered as set with [`Self::with_tool`], by default with the name of the
+/// tool that was called; reads answer with the URI that was read. Prompts
+/// render as an assistant message naming the prompt, then a user message
+/// listing the arguments.
+#[derive(Clone, Default)]
+pub(crate) struct StubServer {
+    pub tools: Vec<Tool>,
+    pub resources: Vec<String>,
+    pub prompts: Vec<Prompt>,
+    pub replies: HashMap<String, StubReply>,
+    pub log: Arc<StubLog>,
+}
+
+impl StubServer {
+    pub fn with_tools(names: &[&str]) -> Self {
+        Self {
+            tools: names.iter().map(|name| tool(name)).collect(),
+            ..Default::default()
+        }
+    }
+
+    /// Adds `tool`, answering its calls with `reply`.
+    pub fn with_tool(mut self, tool: Tool, reply: StubReply) -> Self {
+        self.replies.insert(tool.name.to_string(), reply);
+        self.tools.push(tool);
+        self
+    }
+}
+
+pub(crate) fn tool(name: &str) -> Tool {
+    tool_with_schema(name, json!({ "type": "object" }))
+}
+
+/// A tool whose arguments are described by `schema`.
+pub(crate) fn tool_with_schema(name: &str, schema: Value) -> Tool {
+    let Value::Object(schema) = schema else {
+        panic!("tool schemas are objects");
+    };
+    Tool::new(
+        Cow::Owned(name.to_string()),
+        "A stub tool",
+        Arc::new(schema),
+    )
+}
+
+impl ServerHandler for StubServer {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult {
+            tools: self.tools.clone(),
+            next_cursor: None,
+        })
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.log.calls.lock().unwrap().push(StubCall {
+            name: request.name.to_string(),
+            arguments: request.arguments.clone(),
+            progress_token: context.meta.get_progress_token(),
+        });
+
+        let reply = self.replies.get(request.name.as_ref()).cloned();
+        match reply.unwrap_or(StubReply::Echo) {
+            StubReply::Echo => Ok(CallToolResult::success(vec![Content::text(
+                request.name.to_string(),
+            )])),
+            StubReply::Structured { text, structured } => {
+                let mut result = CallToolResult::success(vec![Content::text(text)]);
+                result.structured_content = Some(structured);
+                Ok(result)
+            }
+            StubReply::Hang => {
+                context.ct.cancelled().await;
+                self.log.cancelled.fetch_add(1, Ordering::SeqCst);
+                Err(McpError::internal_error("cancelled", None))
+            }
+            StubReply::Fail => Err(McpError::internal_error("temporarily unavailable", None)),
+            StubReply::Progress { steps } => {
+                let token = context
+                    .meta
+                    .get_progress_token()
+                    .expect("no progress token");
+                for step in 1..=steps {
+                    context
+                        .peer
+                        .notify_progress(ProgressNotificationParam {
+                            progress_token: token.clone(),
+                            progress: step as f64,
+                            total: Some(steps as f64),
+                            message: Some(format!("step {step}")),
+                        })
+                        .await
+                        .map_err(|e| McpError::internal_error(e.to_string(), None))?;
+                }
+                // rmcp sends notifications from spawned tasks, so without a
+                // pause they may arrive after the response.
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                Ok(CallToolResult::success(vec![Content::text(request.name)]))
+            }
+            StubReply::Elicit => {
+                let request = ServerRequest::CreateElicitationRequest(Request::new(
+                    CreateElicitationRequestParam {
+                        message: "Who is booking?".to_string(),
+                        requested_schema: object(json!({
+                            "type": "object",
+                            "properties": { "name": { "type": "string" } },
+                            "required": ["name"]
+                        })),
+                    },
+                ));
+                let result = match context.peer.send_request(request).await {
+                    Ok(ClientResult::CreateElicitationResult(result)) => result,
+                    Ok(_) => return Err(McpError::internal_error("unexpected response", None)),
+                    Err(e) => return Err(McpError::internal_error(e.to_string(), None)),
+                };
+                let text = match result.content {
+                    Some(content) => content.to_string(),
+                    None => format!("{:?}", result.action),
+                };
+                Ok(CallToolResult::success(vec![Content::text(text)]))
+            }
+        }
+    }
+
+    async fn list_resources(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListResourcesResult, McpError> {
+        Ok(ListResourcesResult {
+            resources: self
+                .resources
+                .iter()
+                .map(|uri| RawResource::new(uri, uri).no_annotation())
+                .collect(),
+            next_cursor: None,
+        })
+    }
+
+    async fn read_resource(
+        &self,
+        request: ReadResourceRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ReadResourceResult, McpError> {
+        if !self.resources.contains(&request.uri) {
+            return Err(McpError::resource_not_found(request.uri, None));
+        }
+        Ok(ReadResourceResult {
+            contents: vec![ResourceContents::text(&request.uri, &request.uri)],
+        })
+    }
+
+    async fn list_prompts(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListPromptsResult, McpError> {
+        Ok(ListPromptsResult {
+            prompts: self.prompts.clone(),
+            next_cursor: None,
+        })
+    }
+
+    async fn get_prompt(
+        &self,
+        request: GetPromptRequestParam,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<GetPromptResult, McpError> {
+        self.log.prompts.lock().unwrap().push(request.name.clone());
+        let Some(prompt) = self.prompts.iter().find(|p| p.name == request.name) else {
+            return Err(McpError::invalid_params(request.name, None));
+        };
+
+        let mut arguments = request
+            .arguments
+            .iter()
+            .flatten()
+            .map(|(name, value)| match value.as_str() {
+                Some(value) => format!("{name}: {value}"),
+                None => format!("{name}: {value}"),
+            })
+            .collect::<Vec<_>>();
+        arguments.sort();
+        Ok(GetPromptResult {
+            description: prompt.description.clone(),
+            messages: vec![
+                PromptMessage::new_text(
+                    PromptMessageRole::Assistant,
+                    format!("You follow the `{}` prompt.", prompt.name),
+                ),
+                PromptMessage::new_text(PromptMessageRole::User, arguments.join("\n")),
+            ],
+        })
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            capabilities: ServerCapabilities::builder()
+                .enable_tools()
+                .enable_resources()
+                .enable_prompts()
+                .build(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A client connected to a [`StubServer`] through an in-memory pipe, and the
+/// server side of the session, which closes the client's transport when
+/// cancelled or dropped.
+pub(crate) async fn connect(
+    server: StubServer,
+) -> (
+    RunningService<RoleClient, AgentClientHandler>,
+    RunningService<RoleServer, StubServer>,
+) {
+    let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+    let (server, client) = tokio::join!(
+        server.serve(server_io),
+        AgentClientHandler::default().serve(client_io)
+    );
+    (client.unwrap(), server.unwrap())
+}
+
+/// A client connected to a [`StubServer`] whose session has ended.
+pub(crate) async fn closed(server: StubServer) -> RunningService<RoleClient, AgentClientHandler> {
+    let (client, server) = connect(server).await;
+    server.cancel().await.unwrap();
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while !client.peer().is_transport_closed() {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    client
+}
+
+/// A client whose session has ended, and which connects to a new session
+/// of its [`StubServer`] when asked to reconnect.
+pub(crate) struct DroppedClient {
+    server: StubServer,
+    session: RwLock<RunningService<RoleClient, AgentClientHandler>>,
+    reconnects: AtomicUsize,
+}
+
+impl DroppedClient {
+    pub async fn new(server: StubServer) -> Self {
+        Self {
+            session: RwLock::new(closed(server.clone()).await),
+            server,
+            reconnects: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn reconnects(&self) -> usize {
+        self.reconnects.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl McpClient for DroppedClient {
+    fn peer(&self) -> Peer<RoleClient> {
+        self.session.read().unwrap().peer().clone()
+    }
+
+    async fn reconnect(&self) -> bool {
+        let (client, server) = connect(self.server.clone()).await;
+        tokio::spawn(server.waiting());
+        *self.session.write().unwrap() = client;
+        self.reconnects.fetch_add(1, Ordering::SeqCst);
+        true
+    }
+}
+
+/// A [`StubServer`] listening on a local port, reached through a stdio
+/// command that pipes its standard streams to the port, so that clients
+/// connect as they would to a real server. Every connection gets its own
+/// session of the server as set at the time.
+#[cfg(unix)]
+pub(crate) struct StubListener {
+    port: u16,
+    server: Arc<Mutex<StubServer>>,
+    sessions: Arc<Mutex<Vec<RunningServiceCancellationToken>>>,
+    connections: Arc<AtomicUsize>,
+    accept: tokio::task::JoinHandle<()>,
+}
+
+#[cfg(unix)]
+impl StubListener {
+    pub async fn start(server: StubServer) -> Self {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Arc::new(Mutex::new(server));
+        let sessions = Arc::new(Mutex::new(Vec::new()));
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        let accept = tokio::spawn({
+            let server = server.clone();
+            let sessions = sessions.clone();
+            let connections = connections.clone();
+            async move {
+                while let Ok((stream, _)) = listener.accept().await {
+                    let server = server.lock().unwrap().clone();
+                    let Ok(session) = server.serve(stream).await else {
+                        continue;
+                    };
+                    connections.fetch_add(1, Ordering::SeqCst);
+                    sessions.lock().unwrap().push(session.cancellation_token());
+                    tokio::spawn(session.waiting());
+                }
+            }
+        });
+
+        Self {
+            port,
+            server,
+            sessions,
+            connections,
+            accept,
+        }
+    }
+
+    /// Runs `bash`, which forwards stdin to the port and the port to stdout.
+    pub fn spec(&self) -> McpServerSpec {
+        McpServerSpec::stdio("bash")
+            .arg("-c")
+            .arg(format!(
+                "exec 3<>/dev/tcp/127.0.0.1/{} || exit 1; cat <&3 & exec cat >&3",
+                self.port
+            ))
+            .into()
+    }
+
+    /// Changes the server that later connections get.
+    pub fn set_server(&self, server: StubServer) {
+        *self.server.lock().unwrap() = server;
+    }
+
+    /// Number of sessions started so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Ends every session, which closes the clients' transports.
+    pub fn drop_sessions(&self) {
+        for session in self.sessions.lock().unwrap().drain(..) {
+            session.cancel();
+        }
+    }
+
+    /// Stops accepting connections, then ends every session.
+    pub fn stop(&self) {
+        self.accept.abort();
+        self.drop_sessions();
+    }
+}
+
+#[cfg(unix)]
+impl Drop for StubListener {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -1,2 +1,4 @@
 pub mod agent;
+pub mod client;
+pub mod server;
 pub mod tool;
@@ -0,0 +1,509 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures_util::StreamExt;
+use langchain_rust::chain::Chain;
+use langchain_rust::memory::SimpleMemory;
+use langchain_rust::prompt::PromptArgs;
+use langchain_rust::schemas::BaseMemory;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, Content, ListToolsResult, PaginatedRequestParam,
+    ProgressNotificationParam, ProgressToken, ServerCapabilities, ServerInfo, Tool, object,
+};
+use rmcp::service::{Peer, RequestContext};
+use rmcp::{ErrorData as McpError, RoleServer, ServerHandler};
+use serde_json::{Value, json};
+
+use crate::agent::executor::{
+    FINISH_LENGTH, FINISH_STOP, FINISH_TOOL_CALL, OpenAIMcpAgentExecutor, is_run_error,
+    is_tool_result,
+};
+use crate::agent::extension::AgentExt;
+
+const DEFAULT_TOOL_NAME: &str = "run_agent";
+const DEFAULT_TOOL_DESCRIPTION: &str = "Runs an agent on a message and returns its final answer. Pass the same conversation_id to continue a conversation.";
+const DEFAULT_MAX_CONVERSATIONS: usize = 1000;
+
+type ExecutorFactory<A> = Arc<dyn Fn() -> OpenAIMcpAgentExecutor<A> + Send + Sync>;
+type ConversationMemory = Arc<tokio::sync::Mutex<dyn BaseMemory>>;
+
+struct Conversation {
+    memory: ConversationMemory,
+    /// Value of [`Conversations::clock`] when the conversation was last used.
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct Conversations {
+    entries: HashMap<String, Conversation>,
+    /// Counts the runs, to tell which conversation was used least recently.
+    clock: u64,
+}
+
+/// Publishes an agent as an MCP server with a single tool.
+///
+/// The tool takes a `message` and an optional `conversation_id`, runs
+/// [`OpenAIMcpAgentExecutor::stream`] and returns the final answer, or an
+/// error result if the run fails. Calls sharing a conversation id share their
+/// chat history, which is kept for the most recently used conversations, see
+/// [`Self::with_max_conversations`]. While the agent runs, each tool call it makes and each
+/// result it gets is sent to the caller as a progress notification whose
+/// message is the call or result as JSON.
+///
+/// A new executor is created for every call, so `make_executor` is where
+/// iteration limits, approvals, etc. are configured.
+pub struct AgentServer<A: AgentExt> {
+    tool_name: String,
+    tool_description: String,
+    make_executor: ExecutorFactory<A>,
+    max_conversations: usize,
+    conversations: Arc<Mutex<Conversations>>,
+}
+
+impl<A: AgentExt> Clone for AgentServer<A> {
+    fn clone(&self) -> Self {
+        Self {
+            tool_name: self.tool_name.clone(),
+            tool_description: self.tool_description.clone(),
+            make_executor: self.make_executor.clone(),
+            max_conversations: self.max_conversations,
+            conversations: self.conversations.clone(),
+        }
+    }
+}
+
+impl<A: AgentExt + 'static> AgentServer<A> {
+    pub fn new<F>(make_executor: F) -> Self
+    where
+        F: Fn() -> OpenAIMcpAgentExecutor<A> + Send + Sync + 'static,
+    {
+        Self {
+            tool_name: DEFAULT_TOOL_NAME.to_string(),
+            tool_description: DEFAULT_TOOL_DESCRIPTION.to_string(),
+            make_executor: Arc::new(make_executor),
+            max_conversations: DEFAULT_MAX_CONVERSATIONS,
+            conversations: Arc::new(Mutex::new(Conversations::default())),
+        }
+    }
+
+    /// Name and description of the published tool, `run_agent` by default.
+    pub fn with_tool(mut self, name: impl ToString, description: impl ToString) -> Self {
+        self.tool_name = name.to_string();
+        self.tool_description = description.to_string();
+        self
+    }
+
+    /// How many conversations keep their chat history, 1000 by default and
+    /// at least 1. Starting one more forgets the least recently used one.
+    pub fn with_max_conversations(mut self, max_conversations: usize) -> Self {
+        self.max_conversations = max_conversations.max(1);
+        self
+    }
+
+    /// Forgets the chat history of `conversation_id`. Returns whether there
+    /// was one.
+    pub fn end_conversation(&self, conversation_id: &str) -> bool {
+        self.conversations
+            .lock()
+            .unwrap()
+            .entries
+            .remove(conversation_id)
+            .is_some()
+    }
+
+    fn tool(&self) -> Tool {
+        let schema = object(json!({
+            "type": "object",
+            "properties": {
+                "message": {
+                    "type": "string",
+                    "description": "The message for the agent"
+                },
+                "conversation_id": {
+                    "type": "string",
+                    "description": "Id of the conversation to continue; omit it for a one-off request"
+                }
+            },
+            "required": ["message"]
+        }));
+        Tool::new(
+            self.tool_name.clone(),
+            self.tool_description.clone(),
+            Arc::new(schema),
+        )
+    }
+
+    fn memory_for(&self, conversation_id: &str) -> ConversationMemory {
+        let mut conversations = self.conversations.lock().unwrap();
+        conversations.clock += 1;
+        let now = conversations.clock;
+
+        if !conversations.entries.contains_key(conversation_id)
+            && conversations.entries.len() >= self.max_conversations
+        {
+            let oldest = conversations
+                .entries
+                .iter()
+                .min_by_key(|(_, conversation)| conversation.last_used)
+                .map(|(id, _)| id.clone());
+            if let Some(oldest) = oldest {
+                tracing::debug!("Forgetting the chat history of conversation {oldest}");
+                conversations.entries.remove(&oldest);
+            }
+        }
+
+        let conversation = conversations
+            .entries
+            .entry(conversation_id.to_string())
+            .or_insert_with(|| Conversation {
+                memory: Arc::new(tokio::sync::Mutex::new(SimpleMemory::new())),
+                last_used: now,
+            });
+        conversation.last_used = now;
+        conversation.memory.clone()
+    }
+
+    async fn run(
+        &self,
+        message: &str,
+        conversation_id: Option<&str>,
+        progress: Option<(Peer<RoleServer>, ProgressToken)>,
+    ) -> Result<CallToolResult, McpError> {
+        let mut executor = (self.make_executor)();
+        let mut input_variables = PromptArgs::new();
+        input_variables.insert("input".to_string(), json!(message));
+        if let Some(conversation_id) = conversation_id {
+            executor = executor.with_memory(self.memory_for(conversation_id));
+            input_variables.insert("conversation_id".to_string(), json!(conversation_id));
+        }
+
+        let mut stream = match executor.stream(input_variables).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                let message = format!("Failed to start agent: {e}");
+                return Ok(CallToolResult::error(vec![Content::text(message)]));
+            }
+        };
+
+        let mut answer = String::new();
+        let mut step = 0u32;
+        while let Some(chunk) = stream.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => return Ok(CallToolResult::error(vec![Content::text(e.to_string())])),
+            };
+            let choice = &chunk.value["choices"][0];
+            let delta = &choice["delta"];
+
+            if is_run_error(delta) {
+                let message = delta["content"]
+                    .as_str()
+                    .or_else(|| delta["error_message"].as_str())
+                    .unwrap_or("Agent run failed");
+                return Ok(CallToolResult::error(vec![Content::text(message)]));
+            }
+
+            if let Some(content) = delta["content"].as_str() {
+                answer.push_str(content);
+            }
+
+            let event = if choice["finish_reason"] == FINISH_TOOL_CALL {
+                delta["tool_calls"].get(0).cloned()
+            } else if is_tool_result(delta) {
+                // Text produced before a tool result is not the final answer.
+                answer.clear();
+                Some(json!({
+                    "tool_call_id": delta["tool_call_id"],
+                    "tool_name": delta["tool_name"],
+                    "is_error": delta["is_error"],
+                    "result": delta["parsed"],
+                }))
+            } else {
+                None
+            };
+            if let (Some(event), Some((peer, token))) = (event, &progress) {
+                step += 1;
+                let notification = ProgressNotificationParam {
+                    progress_token: token.clone(),
+                    progress: step.into(),
+                    total: None,
+                    message: Some(event.to_string()),
+                };
+                if let Err(e) = peer.notify_progress(notification).await {
+                    tracing::warn!("Failed to send progress notification: {e}");
+                }
+            }
+
+            match choice["finish_reason"].as_str() {
+                Some(FINISH_STOP) => break,
+                Some(FINISH_LENGTH) => {
+                    return Ok(CallToolResult::error(vec![Content::text(answer)]));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(CallToolResult::success(vec![Content::text(answer)]))
+    }
+}
+
+impl<A: AgentExt + 'static> ServerHandler for AgentServer<A> {
+    async fn list_tools(
+        &self,
+        _request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        Ok(ListToolsResult::with_all_items(vec![self.tool()]))
+    }
+
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        if request.name != self.tool_name {
+            return Err(McpError::invalid_params(
+                format!("Unknown tool `{}`", request.name),
+                None,
+            ));
+        }
+
+        let arguments = request.arguments.unwrap_or_default();
+        let Some(message) = arguments.get("message").and_then(Value::as_str) else {
+            return Err(McpError::invalid_params(
+                "`message` is required and must be a string",
+                None,
+            ));
+        };
+        let conversation_id = arguments.get("conversation_id").and_then(Value::as_str);
+        let progress = context
+            .meta
+            .get_progress_token()
+            .map(|token| (context.peer.clone(), token));
+
+        tokio::select! {
+            result = self.run(message, conversation_id, progress) => result,
+            _ = context.ct.cancelled() => Err(McpError::internal_error(
+                "Agent run was cancelled",
+                None,
+            )),
+        }
+    }
+
+    fn get_info(&self) -> ServerInfo {
+        ServerInfo {
+            instructions: Some(self.tool_description.clone()),
+            capabilities: ServerCapabilities::builder().enable_tools().build(),
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use langchain_rust::agent::{Agent, AgentError};
+    use langchain_rust::schemas::{AgentAction, AgentEvent, AgentFinish};
+    use langchain_rust::tools::Tool as LangchainTool;
+    use rmcp::service::NotificationContext;
+    use rmcp::{ClientHandler, RoleClient, ServiceExt};
+    use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender, unbounded_channel};
+
+    use super::*;
+    use crate::agent::extension::{AgentEventChunk, AgentStream, DeltaEvent};
+    use crate::agent::intermediate::IntermediateStep;
+    use crate::tool::extension::{ToolError, ToolExt, ToolOutput};
+    use crate::tool::native::FnTool;
+    use crate::tool::registry::{ToolRegistry, ToolSource};
+
+    /// Looks the answer up with its tool, then gives the observation back.
+    struct LookupAgent {
+        tool: Arc<dyn ToolExt>,
+    }
+
+    impl LookupAgent {
+        fn new(result: fn() -> Result<String, ToolError>) -> Self {
+            let tool = FnTool::new(
+                "lookup",
+                "Looks something up",
+                json!({ "type": "object" }),
+                move |_| async move { result() },
+            );
+            Self {
+                tool: Arc::new(tool),
+            }
+        }
+
+        fn next_events(steps: &[impl IntermediateStep]) -> Vec<AgentEventChunk> {
+            match steps.first() {
+                None => vec![
+                    AgentEventChunk::Delta(DeltaEvent::Content("Let me look.".to_string())),
+                    AgentEventChunk::Final(AgentEvent::Action(vec![AgentAction {
+                        tool: "lookup".to_string(),
+                        tool_input: "{}".to_string(),
+                        log: json!({ "tool_id": "call_1", "tools": "[]" }).to_string(),
+                    }])),
+                ],
+                Some(step) => {
+                    let output = format!("The answer is {}", step.observation());
+                    vec![
+                        AgentEventChunk::Delta(DeltaEvent::Content(output.clone())),
+                        AgentEventChunk::Final(AgentEvent::Finish(AgentFinish { output })),
+                    ]
+                }
+            }
+        }
+    }
+
+    #[async_trait]
+    impl Agent for LookupAgent {
+        async fn plan(
+            &self,
+            _steps: &[(AgentAction, String)],
+            _inputs: PromptArgs,
+        ) -> Result<AgentEvent, AgentError> {
+            unreachable!("the server streams its runs")
+        }
+
+        fn get_tools(&self) -> Vec<Arc<dyn LangchainTool>> {
+            vec![self.tool.clone()]
+        }
+    }
+
+    #[async_trait]
+    impl AgentExt for LookupAgent {
+        fn tool_registry(&self) -> ToolRegistry {
+            let mut registry = ToolRegistry::new();
+            registry
+                .register(ToolSource::Native, self.tool.clone())
+                .unwrap();
+            registry
+        }
+
+        async fn plan_with_steps(
+            &self,
+            _steps: &[impl IntermediateStep],
+            _inputs: PromptArgs,
+        ) -> Result<AgentEvent, AgentError> {
+            unreachable!("the server streams its runs")
+        }
+
+        async fn plan_stream(
+            &self,
+            steps: &[impl IntermediateStep],
+            _inputs: PromptArgs,
+        ) -> Result<AgentStream, AgentError> {
+            let events = Self::next_events(steps).into_iter().map(Ok);
+            Ok(Box::pin(futures_util::stream::iter(events)))
+        }
+    }
+
+    fn server(result: fn() -> Result<String, ToolError>) -> AgentServer<LookupAgent> {
+        AgentServer::new(move || {
+            OpenAIMcpAgentExecutor::new(Arc::new(LookupAgent::new(result)), "test")
+                .with_break_if_error(true)
+        })
+    }
+
+    /// Records the progress notifications it gets.
+    struct ProgressRecorder(UnboundedSender<ProgressNotificationParam>);
+
+    impl ClientHandler for ProgressRecorder {
+        async fn on_progress(
+            &self,
+            params: ProgressNotificationParam,
+            _context: NotificationContext<RoleClient>,
+        ) {
+            let _ = self.0.send(params);
+        }
+    }
+
+    /// Calls the agent tool of `server` with `message`, returning the result
+    /// and the progress notifications sent for the call.
+    async fn call(
+        server: AgentServer<LookupAgent>,
+        message: &str,
+    ) -> (CallToolResult, UnboundedReceiver<ProgressNotificationParam>) {
+        let (client_io, server_io) = tokio::io::duplex(64 * 1024);
+        let (progress_tx, progress_rx) = unbounded_channel();
+        let (server, client) = tokio::join!(
+            server.serve(server_io),
+            ProgressRecorder(progress_tx).serve(client_io)
+        );
+        tokio::spawn(server.unwrap().waiting());
+
+        let result = client
+            .unwrap()
+            .call_tool(CallToolRequestParam {
+                name: DEFAULT_TOOL_NAME.into(),
+                arguments: Some(object(json!({ "message": message }))),
+            })
+            .await
+            .unwrap();
+        (result, progress_rx)
+    }
+
+    async fn next_progress(progress: &mut UnboundedReceiver<ProgressNotificationParam>) -> Value {
+        let notification = tokio::time::timeout(Duration::from_secs(5), progress.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        serde_json::from_str(&notification.message.unwrap()).unwrap()
+    }
+
+    fn text(result: &CallToolResult) -> &str {
+        &result.content[0].as_text().unwrap().text
+    }
+
+    #[tokio::test]
+    async fn returns_the_final_answer_and_reports_tool_calls() {
+        let (result, mut progress) = call(server(|| Ok("42".to_string())), "What is it?").await;
+
+        assert_eq!(result.is_error, Some(false));
+        // Text produced before the tool result is not part of the answer.
+        assert_eq!(text(&result), "The answer is 42");
+
+        let tool_call = next_progress(&mut progress).await;
+        assert_eq!(tool_call["id"], "call_1");
+        assert_eq!(tool_call["function"]["name"], "lookup");
+        let tool_result = next_progress(&mut progress).await;
+        assert_eq!(tool_result["tool_call_id"], "call_1");
+        assert_eq!(tool_result["result"], 42);
+        assert_eq!(tool_result["is_error"], false);
+    }
+
+    #[tokio::test]
+    async fn failed_runs_return_an_error_result() {
+        let failing = || {
+            Err(ToolError::Tool(ToolOutput::from(
+                "no such record".to_string(),
+            )))
+        };
+        let (result, mut progress) = call(server(failing), "What is it?").await;
+
+        assert_eq!(result.is_error, Some(true));
+        assert!(
+            text(&result).contains("no such record"),
+            "{}",
+            text(&result)
+        );
+        // The call was still reported before it failed.
+        assert_eq!(next_progress(&mut progress).await["id"], "call_1");
+    }
+
+    #[test]
+    fn forgets_the_least_recently_used_conversations() {
+        let server = server(|| Ok("42".to_string())).with_max_conversations(2);
+        let first = server.memory_for("first");
+        server.memory_for("second");
+        // Continuing `first` makes `second` the least recently used.
+        assert!(Arc::ptr_eq(&first, &server.memory_for("first")));
+        server.memory_for("third");
+
+        assert!(!server.end_conversation("second"));
+        assert!(server.end_conversation("first"));
+        assert!(server.end_conversation("third"));
+    }
+}
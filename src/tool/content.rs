@@ -0,0 +1,211 @@
+use langchain_rust::schemas::ImageContent;
+use rmcp::model::{Content, RawContent, ResourceContents};
+use serde::Serialize;
+
+use crate::tool::extension::ToolOutput;
+
+/// A single piece of tool output, kept in a shape the executor can forward
+/// to the model and to stream clients without losing media.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ContentPart {
+    Text { text: String },
+    Image { data: String, mime_type: String },
+    Audio { data: String, mime_type: String },
+}
+
+impl ContentPart {
+    pub fn text(&self) -> Option<&str> {
+        match self {
+            ContentPart::Text { text } => Some(text),
+            _ => None,
+        }
+    }
+
+    /// Returns the part as a langchain image (base64 data url) if it is one.
+    pub fn as_image(&self) -> Option<ImageContent> {
+        match self {
+            ContentPart::Image { data, mime_type } => Some(ImageContent {
+                image_url: format!("data:{mime_type};base64,{data}"),
+                detail: None,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Converts the content blocks of an MCP tool result into a [`ToolOutput`].
+///
+/// Text is kept as-is, images and audio are kept as media parts (with a short
+/// placeholder in the text observation), embedded text resources are inlined
+/// and binary resources / resource links are described.
+pub fn convert_contents(contents: Vec<Content>) -> ToolOutput {
+    let mut parts = Vec::with_capacity(contents.len());
+    for content in contents {
+        match content.raw {
+            RawContent::Text(text) => parts.push(ContentPart::Text { text: text.text }),
+            RawContent::Image(image) => parts.push(ContentPart::Image {
+                data: image.data,
+                mime_type: image.mime_type,
+            }),
+            RawContent::Audio(audio) => parts.push(ContentPart::Audio {
+                data: audio.data,
+                mime_type: audio.mime_type,
+            }),
+            RawContent::Resource(embedded) => {
+                parts.push(ContentPart::Text {
+                    text: describe_resource(&embedded.resource),
+                });
+            }
+            RawContent::ResourceLink(link) => {
+                let mut text = format!("[resource link: {} <{}>", link.name, link.uri);
+                if let Some(mime_type) = &link.mime_type {
+                    text.push_str(&format!(" ({mime_type})"));
+                }
+                text.push(']');
+                if let Some(description) = &link.description {
+                    text.push_str(&format!(" {description}"));
+                }
+                parts.push(ContentPart::Text { text });
+            }
+        }
+    }
+
+    ToolOutput::from_parts(parts)
+}
+
+/// Converts the result of `resources/read` into a [`ToolOutput`]. Image blobs
+/// are kept as media parts, other contents are handled like embedded
+/// resources in tool results.
+pub fn convert_resource_contents(contents: Vec<ResourceContents>) -> ToolOutput {
+    let parts = contents
+        .into_iter()
+        .map(|resource| match resource {
+            ResourceContents::BlobResourceContents {
+                mime_type: Some(mime_type),
+                blob,
+                ..
+            } if mime_type.starts_with("image/") => ContentPart::Image {
+                data: blob,
+                mime_type,
+            },
+            resource => ContentPart::Text {
+                text: describe_resource(&resource),
+            },
+        })
+        .collect();
+
+    ToolOutput::from_parts(parts)
+}
+
+fn describe_resource(resource: &ResourceContents) -> String {
+    match resource {
+        ResourceContents::TextResourceContents { uri, text, .. } => {
+            format!("[resource: {uri}]\n{text}")
+        }
+        ResourceContents::BlobResourceContents {
+            uri,
+            mime_type,
+            blob,
+            ..
+        } => {
+            // base64 encodes 3 bytes in 4 chars
+            let approx_bytes = blob.len() / 4 * 3;
+            format!(
+                "[binary resource: {uri} ({}), ~{approx_bytes} bytes]",
+                mime_type.as_deref().unwrap_or("application/octet-stream")
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::model::{RawAudioContent, RawResource};
+
+    use super::*;
+
+    #[test]
+    fn keeps_media_parts_with_placeholders() {
+        let audio = RawAudioContent {
+            data: "UklGRg==".to_string(),
+            mime_type: "audio/wav".to_string(),
+        };
+        let output = convert_contents(vec![
+            Content::text("A chart: "),
+            Content::image("iVBORw0KGgo=", "image/png"),
+            Content::new(RawContent::Audio(audio), None),
+        ]);
+
+        assert_eq!(
+            output.content,
+            "A chart: [image: image/png][audio: audio/wav]"
+        );
+        assert_eq!(
+            output.parts,
+            vec![
+                ContentPart::Text {
+                    text: "A chart: ".to_string()
+                },
+                ContentPart::Image {
+                    data: "iVBORw0KGgo=".to_string(),
+                    mime_type: "image/png".to_string()
+                },
+                ContentPart::Audio {
+                    data: "UklGRg==".to_string(),
+                    mime_type: "audio/wav".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            output.parts[1].as_image().unwrap().image_url,
+            "data:image/png;base64,iVBORw0KGgo="
+        );
+    }
+
+    #[test]
+    fn inlines_text_resources_and_describes_blobs() {
+        let output = convert_contents(vec![
+            Content::embedded_text("file:///notes.md", "# Notes"),
+            Content::resource(ResourceContents::BlobResourceContents {
+                uri: "file:///data.bin".to_string(),
+                mime_type: None,
+                blob: "AAAAAAAA".to_string(),
+                meta: None,
+            }),
+        ]);
+
+        assert_eq!(
+            output.content,
+            "[resource: file:///notes.md]\n# Notes\
+             [binary resource: file:///data.bin (application/octet-stream), ~6 bytes]"
+        );
+        assert!(output.parts.iter().all(|part| part.text().is_some()));
+    }
+
+    #[test]
+    fn describes_resource_links() {
+        let mut link = RawResource::new("file:///report.pdf", "report");
+        link.mime_type = Some("application/pdf".to_string());
+        link.description = Some("Quarterly report".to_string());
+        let output = convert_contents(vec![Content::resource_link(link)]);
+
+        assert_eq!(
+            output.content,
+            "[resource link: report <file:///report.pdf> (application/pdf)] Quarterly report"
+        );
+    }
+
+    #[test]
+    fn keeps_image_resources_as_media() {
+        let output = convert_resource_contents(vec![ResourceContents::BlobResourceContents {
+            uri: "file:///chart.png".to_string(),
+            mime_type: Some("image/png".to_string()),
+            blob: "iVBORw0KGgo=".to_string(),
+            meta: None,
+        }]);
+
+        assert_eq!(output.content, "[image: image/png]");
+        assert!(output.parts[0].as_image().is_some());
+    }
+}
@@ -0,0 +1,252 @@
+use std::fmt::{Display, Formatter};
+
+use async_trait::async_trait;
+use langchain_rust::schemas::Message;
+use langchain_rust::tools::Tool;
+use rmcp::ServiceError;
+use rmcp::model::ToolAnnotations;
+use serde::Serialize;
+use serde_json::Value;
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::client::sampling::SamplingScope;
+use crate::tool::content::ContentPart;
+
+#[async_trait]
+pub trait ToolExt: Tool {
+    /// Like [`Tool::call`], but keeps the full output of the tool (media parts
+    /// included) and returns an error type that can cross task boundaries.
+    async fn call_ext(&self, input: &str, ctx: ToolCallContext) -> Result<ToolOutput, ToolError>;
+
+    /// Behaviour hints (read-only, destructive, ...) declared for the tool.
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        None
+    }
+}
+
+/// Per-call information handed to a tool by the executor.
+#[derive(Debug, Clone, Default)]
+pub struct ToolCallContext {
+    pub tool_call_id: String,
+    /// Where to report progress, if the caller wants it.
+    pub progress: Option<UnboundedSender<ToolProgress>>,
+    /// The run that sampling requests made for the call count against.
+    pub(crate) sampling: Option<SamplingScope>,
+}
+
+impl ToolCallContext {
+    pub fn new(tool_call_id: impl ToString) -> Self {
+        Self {
+            tool_call_id: tool_call_id.to_string(),
+            progress: None,
+            sampling: None,
+        }
+    }
+
+    pub fn with_progress(mut self, progress: UnboundedSender<ToolProgress>) -> Self {
+        self.progress = Some(progress);
+        self
+    }
+
+    pub(crate) fn with_sampling(mut self, sampling: Option<SamplingScope>) -> Self {
+        self.sampling = sampling;
+        self
+    }
+
+    pub fn report_progress(&self, progress: f64, total: Option<f64>, message: Option<String>) {
+        if let Some(tx) = &self.progress {
+            let _ = tx.send(ToolProgress {
+                tool_call_id: self.tool_call_id.clone(),
+                progress,
+                total,
+                message,
+            });
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ToolProgress {
+    pub tool_call_id: String,
+    pub progress: f64,
+    pub total: Option<f64>,
+    pub message: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ToolOutput {
+    /// Text observation handed to the model.
+    pub content: String,
+    pub parts: Vec<ContentPart>,
+    /// Typed result of tools that return `structured_content`.
+    pub structured: Option<Value>,
+}
+
+impl ToolOutput {
+    pub fn from_parts(parts: Vec<ContentPart>) -> Self {
+        let mut content = String::default();
+        for part in &parts {
+            match part {
+                ContentPart::Text { text } => content.push_str(text),
+                ContentPart::Image { mime_type, .. } => {
+                    content.push_str(&format!("[image: {mime_type}]"))
+                }
+                ContentPart::Audio { mime_type, .. } => {
+                    content.push_str(&format!("[audio: {mime_type}]"))
+                }
+            }
+        }
+
+        Self {
+            content,
+            parts,
+            structured: None,
+        }
+    }
+
+    /// Builds the tool message for this output. Tool messages only carry
+    /// text: images are sent in [`Self::image_message`], and audio only
+    /// reaches the model as its `[audio: ...]` placeholder.
+    pub fn to_tool_message(&self, tool_call_id: impl Into<String>) -> Message {
+        Message::new_tool_message(&self.content, tool_call_id.into())
+    }
+
+    /// A user message with the images of this output, if it has any. Chat
+    /// APIs only take images from the user, so it is sent after the tool
+    /// messages of the turn.
+    pub fn image_message(&self) -> Option<Message> {
+        let images = self
+            .parts
+            .iter()
+            .filter_map(ContentPart::as_image)
+            .collect::<Vec<_>>();
+        (!images.is_empty()).then(|| Message::new_human_message_with_images(images))
+    }
+}
+
+impl From<String> for ToolOutput {
+    fn from(content: String) -> Self {
+        Self {
+            parts: vec![ContentPart::Text {
+                text: content.clone(),
+            }],
+            content,
+            structured: None,
+        }
+    }
+}
+
+impl From<Value> for ToolOutput {
+    fn from(structured: Value) -> Self {
+        let mut output = Self::from(structured.to_string());
+        output.structured = Some(structured);
+        output
+    }
+}
+
+#[derive(Debug)]
+pub enum ToolError {
+    /// The MCP request itself failed (transport, protocol, ...).
+    Service(ServiceError),
+    /// The tool ran but reported a failure (`is_error` in the call result),
+    /// with its output.
+    Tool(ToolOutput),
+    /// The tool did not answer in time and the request was cancelled.
+    Timeout(String),
+    /// The arguments produced by the model did not match the tool's input
+    /// schema; the tool was not called.
+    InvalidArguments(String),
+    /// The tool result did not match the tool's declared output schema.
+    InvalidOutput(String),
+    /// The call was rejected during human review; the tool was not called.
+    Rejected(String),
+    Other(String),
+}
+
+impl Display for ToolError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolError::Service(e) => write!(f, "{e}"),
+            ToolError::Tool(output) => write!(f, "{}", output.content),
+            ToolError::Timeout(msg) => write!(f, "{msg}"),
+            ToolError::InvalidArguments(msg) => write!(f, "{msg}"),
+            ToolError::InvalidOutput(msg) => write!(f, "{msg}"),
+            ToolError::Rejected(msg) => write!(f, "{msg}"),
+            ToolError::Other(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for ToolError {}
+
+impl ToolError {
+    /// Whether the error was reported by the tool rather than by the transport.
+    pub fn is_tool_error(&self) -> bool {
+        matches!(self, ToolError::Tool(_))
+    }
+
+    /// Whether the model should get the error as the call's observation even
+    /// when the executor breaks on errors: the call timed out, or was not made
+    /// because of its arguments or a rejection.
+    pub fn is_recoverable(&self) -> bool {
+        matches!(
+            self.kind(),
+            ToolErrorKind::Timeout | ToolErrorKind::InvalidArguments | ToolErrorKind::Rejected
+        )
+    }
+
+    /// The observation the model gets for the failed call. Errors reported by
+    /// the tool keep its output, media included; other failures say that the
+    /// tool could not be called.
+    pub fn into_observation(self) -> ToolOutput {
+        match self {
+            ToolError::Tool(mut output) => {
+                output.content = format!("The tool reported an error: {}", output.content);
+                output
+            }
+            err => ToolOutput::from(format!("The tool call failed: {err}")),
+        }
+    }
+
+    pub fn kind(&self) -> ToolErrorKind {
+        match self {
+            ToolError::Service(ServiceError::TransportClosed) => ToolErrorKind::ConnectionClosed,
+            ToolError::Service(ServiceError::TransportSend(_)) => ToolErrorKind::Transport,
+            ToolError::Service(ServiceError::Timeout { .. }) | ToolError::Timeout(_) => {
+                ToolErrorKind::Timeout
+            }
+            ToolError::Service(ServiceError::McpError(_)) => ToolErrorKind::Protocol,
+            ToolError::Service(_) => ToolErrorKind::Other,
+            ToolError::Tool(_) => ToolErrorKind::Tool,
+            ToolError::InvalidArguments(_) => ToolErrorKind::InvalidArguments,
+            ToolError::InvalidOutput(_) => ToolErrorKind::InvalidOutput,
+            ToolError::Rejected(_) => ToolErrorKind::Rejected,
+            ToolError::Other(_) => ToolErrorKind::Other,
+        }
+    }
+}
+
+/// Coarse classification of [`ToolError`]s, used to decide what is retryable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToolErrorKind {
+    /// The request could not be written to the MCP server.
+    Transport,
+    /// The connection to the MCP server is closed and could not be
+    /// re-established. The request may or may not have reached the server.
+    ConnectionClosed,
+    Timeout,
+    /// The server answered with a JSON-RPC error.
+    Protocol,
+    /// The tool reported `is_error`.
+    Tool,
+    InvalidArguments,
+    InvalidOutput,
+    Rejected,
+    Other,
+}
+
+impl From<ServiceError> for ToolError {
+    fn from(e: ServiceError) -> Self {
+        ToolError::Service(e)
+    }
+}
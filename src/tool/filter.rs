@@ -0,0 +1,200 @@
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use regex::Regex;
+use serde::Serialize;
+
+pub type ToolPredicate = Arc<dyn Fn(&rmcp::model::Tool) -> bool + Send + Sync>;
+
+/// A rule matching tools, native or from MCP servers.
+#[derive(Clone)]
+pub enum ToolMatcher {
+    /// Shell-style pattern (`*` and `?`) on the tool name.
+    Glob(String, Regex),
+    Regex(Regex),
+    Predicate(ToolPredicate),
+    /// Tools annotated with `readOnlyHint: true`.
+    ReadOnly,
+    /// Tools that are read-only or annotated with `destructiveHint: false`.
+    NonDestructive,
+}
+
+impl ToolMatcher {
+    pub fn glob(pattern: &str) -> Self {
+        let escaped = regex::escape(pattern)
+            .replace(r"\*", ".*")
+            .replace(r"\?", ".");
+        let regex = Regex::new(&format!("^{escaped}$")).expect("escaped glob is a valid regex");
+        ToolMatcher::Glob(pattern.to_string(), regex)
+    }
+
+    /// Name-based matchers are checked against both the model-facing name
+    /// (`github__search`) and the MCP tool name (`search`).
+    pub fn matches(&self, name: &str, tool: &rmcp::model::Tool) -> bool {
+        match self {
+            ToolMatcher::Glob(_, regex) | ToolMatcher::Regex(regex) => {
+                regex.is_match(name) || regex.is_match(&tool.name)
+            }
+            ToolMatcher::Predicate(predicate) => predicate(tool),
+            ToolMatcher::ReadOnly => is_read_only(tool),
+            ToolMatcher::NonDestructive => {
+                // Per the MCP spec `destructiveHint` defaults to true.
+                is_read_only(tool)
+                    || tool
+                        .annotations
+                        .as_ref()
+                        .and_then(|a| a.destructive_hint)
+                        .is_some_and(|destructive| !destructive)
+            }
+        }
+    }
+}
+
+impl Debug for ToolMatcher {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolMatcher::Glob(pattern, _) => write!(f, "glob `{pattern}`"),
+            ToolMatcher::Regex(regex) => write!(f, "regex `{}`", regex.as_str()),
+            ToolMatcher::Predicate(_) => write!(f, "predicate"),
+            ToolMatcher::ReadOnly => write!(f, "read-only"),
+            ToolMatcher::NonDestructive => write!(f, "non-destructive"),
+        }
+    }
+}
+
+fn is_read_only(tool: &rmcp::model::Tool) -> bool {
+    tool.annotations
+        .as_ref()
+        .and_then(|a| a.read_only_hint)
+        .unwrap_or(false)
+}
+
+/// Decides which MCP tools an agent gets to see.
+///
+/// A tool is kept when it matches at least one `allow` rule (or there are
+/// none), no `deny` rule, and every `require` rule.
+#[derive(Debug, Clone, Default)]
+pub struct ToolFilter {
+    pub allow: Vec<ToolMatcher>,
+    pub deny: Vec<ToolMatcher>,
+    pub require: Vec<ToolMatcher>,
+}
+
+impl ToolFilter {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty() && self.deny.is_empty() && self.require.is_empty()
+    }
+
+    /// Returns why the tool should be dropped, or `None` to keep it.
+    pub fn check(&self, name: &str, tool: &rmcp::model::Tool) -> Option<String> {
+        if !self.allow.is_empty() && !self.allow.iter().any(|m| m.matches(name, tool)) {
+            return Some("not matched by any allow rule".to_string());
+        }
+        if let Some(rule) = self.deny.iter().find(|m| m.matches(name, tool)) {
+            return Some(format!("denied by {rule:?}"));
+        }
+        if let Some(rule) = self.require.iter().find(|m| !m.matches(name, tool)) {
+            return Some(format!("does not satisfy {rule:?}"));
+        }
+        None
+    }
+}
+
+/// A tool left out of an agent by its [`ToolFilter`].
+#[derive(Debug, Clone, Serialize)]
+pub struct DroppedTool {
+    pub name: String,
+    pub server: Option<String>,
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+
+    use rmcp::model::{Tool, ToolAnnotations};
+    use serde_json::json;
+
+    use super::*;
+
+    fn tool(name: &str, annotations: Option<ToolAnnotations>) -> Tool {
+        let schema = json!({ "type": "object" }).as_object().unwrap().clone();
+        let mut tool = Tool::new(Cow::Owned(name.to_string()), "", Arc::new(schema));
+        tool.annotations = annotations;
+        tool
+    }
+
+    fn annotated(read_only: Option<bool>, destructive: Option<bool>) -> Option<ToolAnnotations> {
+        Some(ToolAnnotations {
+            read_only_hint: read_only,
+            destructive_hint: destructive,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn globs_escape_regex_metacharacters() {
+        let glob = ToolMatcher::glob("get.item?(v*)");
+        assert!(glob.matches("get.items(v2)", &tool("other", None)));
+        assert!(glob.matches("get.item_(v)", &tool("other", None)));
+        assert!(!glob.matches("getxitems(v2)", &tool("other", None)));
+        assert!(!glob.matches("get.item(v2)", &tool("other", None)));
+        // Patterns match the whole name.
+        assert!(!ToolMatcher::glob("get").matches("get_item", &tool("other", None)));
+    }
+
+    #[test]
+    fn names_match_with_or_without_namespace() {
+        let glob = ToolMatcher::glob("search");
+        assert!(glob.matches("github__search", &tool("search", None)));
+        assert!(ToolMatcher::glob("github__*").matches("github__search", &tool("search", None)));
+        assert!(!glob.matches("github__find", &tool("find", None)));
+    }
+
+    #[test]
+    fn deny_rules_win_over_allow_rules() {
+        let filter = ToolFilter {
+            allow: vec![ToolMatcher::glob("get_*")],
+            deny: vec![ToolMatcher::glob("*_admin")],
+            require: Vec::new(),
+        };
+
+        assert_eq!(filter.check("get_user", &tool("get_user", None)), None);
+        assert_eq!(
+            filter.check("get_admin", &tool("get_admin", None)).unwrap(),
+            "denied by glob `*_admin`"
+        );
+        assert_eq!(
+            filter.check("set_user", &tool("set_user", None)).unwrap(),
+            "not matched by any allow rule"
+        );
+    }
+
+    #[test]
+    fn tools_without_annotations_are_neither_read_only_nor_non_destructive() {
+        let read_only = ToolFilter {
+            require: vec![ToolMatcher::ReadOnly],
+            ..Default::default()
+        };
+        let non_destructive = ToolFilter {
+            require: vec![ToolMatcher::NonDestructive],
+            ..Default::default()
+        };
+
+        let bare = tool("bare", None);
+        assert!(read_only.check("bare", &bare).is_some());
+        assert!(non_destructive.check("bare", &bare).is_some());
+
+        // `destructiveHint` defaults to true, even with other annotations.
+        let hinted = tool("hinted", annotated(None, None));
+        assert!(non_destructive.check("hinted", &hinted).is_some());
+
+        let additive = tool("additive", annotated(Some(false), Some(false)));
+        assert!(read_only.check("additive", &additive).is_some());
+        assert_eq!(non_destructive.check("additive", &additive), None);
+
+        let reader = tool("reader", annotated(Some(true), None));
+        assert_eq!(read_only.check("reader", &reader), None);
+        assert_eq!(non_destructive.check("reader", &reader), None);
+    }
+}
@@ -1 +1,9 @@
+pub mod content;
+pub mod extension;
+pub mod filter;
+pub mod native;
+pub mod registry;
+pub mod resource;
+pub mod retry;
 pub mod rmcp;
+pub mod schema;
@@ -0,0 +1,341 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use langchain_rust::tools::Tool;
+use rmcp::model::{JsonObject, ToolAnnotations};
+use serde_json::{Map, Value};
+
+use crate::tool::extension::{ToolCallContext, ToolError, ToolExt, ToolOutput};
+use crate::tool::schema::SchemaValidator;
+
+/// Exposes a plain langchain [`Tool`] to the agent. Like an MCP tool without
+/// hints, it is considered destructive unless annotated otherwise.
+pub struct LangchainTool {
+    tool: Arc<dyn Tool>,
+    annotations: Option<ToolAnnotations>,
+}
+
+impl LangchainTool {
+    pub fn new(tool: Arc<dyn Tool>) -> Self {
+        Self {
+            tool,
+            annotations: None,
+        }
+    }
+
+    /// Declares behaviour hints, used by annotation filters and approvals.
+    pub fn with_annotations(mut self, annotations: ToolAnnotations) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+}
+
+#[async_trait]
+impl Tool for LangchainTool {
+    fn name(&self) -> String {
+        self.tool.name()
+    }
+
+    fn description(&self) -> String {
+        self.tool.description()
+    }
+
+    fn parameters(&self) -> Value {
+        self.tool.parameters()
+    }
+
+    async fn run(&self, input: Value) -> Result<String, Box<dyn std::error::Error>> {
+        self.tool.run(input).await
+    }
+
+    async fn parse_input(&self, input: &str) -> Value {
+        self.tool.parse_input(input).await
+    }
+}
+
+#[async_trait]
+impl ToolExt for LangchainTool {
+    async fn call_ext(&self, input: &str, _ctx: ToolCallContext) -> Result<ToolOutput, ToolError> {
+        match self.tool.call(input).await {
+            Ok(output) => Ok(ToolOutput::from(output)),
+            Err(e) => Err(ToolError::Tool(ToolOutput::from(e.to_string()))),
+        }
+    }
+
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        self.annotations.clone()
+    }
+}
+
+type ToolFuture = Pin<Box<dyn Future<Output = Result<ToolOutput, ToolError>> + Send>>;
+type ToolFn = Arc<dyn Fn(Value, ToolCallContext) -> ToolFuture + Send + Sync>;
+
+/// A tool backed by an async closure, described by a JSON schema.
+///
+/// Arguments are validated against the schema before the closure runs. The
+/// tool is considered destructive unless annotated otherwise.
+pub struct FnTool {
+    name: String,
+    description: String,
+    schema: JsonObject,
+    validator: Option<SchemaValidator>,
+    annotations: Option<ToolAnnotations>,
+    func: ToolFn,
+}
+
+impl FnTool {
+    pub fn new<F, Fut, T>(
+        name: impl ToString,
+        description: impl ToString,
+        schema: Value,
+        func: F,
+    ) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, ToolError>> + Send + 'static,
+        T: Into<ToolOutput>,
+    {
+        Self::with_context(name, description, schema, move |input, _ctx| func(input))
+    }
+
+    /// Like [`Self::new`], but the closure also gets the call context, e.g.
+    /// to report progress.
+    pub fn with_context<F, Fut, T>(
+        name: impl ToString,
+        description: impl ToString,
+        schema: Value,
+        func: F,
+    ) -> Self
+    where
+        F: Fn(Value, ToolCallContext) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<T, ToolError>> + Send + 'static,
+        T: Into<ToolOutput>,
+    {
+        let schema = match schema {
+            Value::Object(schema) => schema,
+            other => {
+                tracing::warn!("tool schema must be a JSON object, got {other}");
+                JsonObject::new()
+            }
+        };
+        let func: ToolFn = Arc::new(move |input, ctx| {
+            let fut = func(input, ctx);
+            Box::pin(async move { fut.await.map(Into::into) })
+        });
+
+        Self {
+            name: name.to_string(),
+            description: description.to_string(),
+            validator: SchemaValidator::compile(&schema),
+            schema,
+            annotations: None,
+            func,
+        }
+    }
+
+    /// Declares behaviour hints, used by annotation filters and approvals.
+    pub fn with_annotations(mut self, annotations: ToolAnnotations) -> Self {
+        self.annotations = Some(annotations);
+        self
+    }
+
+    fn validate_input(&self, input: &Value) -> Result<(), ToolError> {
+        match &self.validator {
+            Some(validator) => validator.validate_arguments(&self.name, input),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Tool for FnTool {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        self.description.clone()
+    }
+
+    fn parameters(&self) -> Value {
+        Value::Object(self.schema.clone())
+    }
+
+    async fn run(&self, input: Value) -> Result<String, Box<dyn std::error::Error>> {
+        self.validate_input(&input)?;
+        Ok((self.func)(input, ToolCallContext::default())
+            .await?
+            .content)
+    }
+
+    async fn parse_input(&self, input: &str) -> Value {
+        match serde_json::from_str::<Map<String, Value>>(input) {
+            Ok(parsed_input) => Value::Object(parsed_input),
+            Err(_) => serde_json::json!({
+                "value": input,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolExt for FnTool {
+    async fn call_ext(&self, input: &str, ctx: ToolCallContext) -> Result<ToolOutput, ToolError> {
+        let input = self.parse_input(input).await;
+        self.validate_input(&input)?;
+        (self.func)(input, ctx).await
+    }
+
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        self.annotations.clone()
+    }
+}
+
+/// Describes a tool in MCP terms, so native tools go through the same
+/// filters as MCP tools.
+pub(crate) fn describe(tool: &dyn ToolExt) -> rmcp::model::Tool {
+    let schema = match tool.parameters() {
+        Value::Object(schema) => schema,
+        _ => JsonObject::new(),
+    };
+    let mut descriptor = rmcp::model::Tool::new(tool.name(), tool.description(), Arc::new(schema));
+    descriptor.annotations = tool.annotations();
+    descriptor
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use serde_json::json;
+
+    use super::*;
+    use crate::agent::approval::ApprovalPolicy;
+    use crate::tool::filter::{ToolFilter, ToolMatcher};
+
+    fn add_tool(calls: Arc<AtomicUsize>) -> FnTool {
+        FnTool::new(
+            "add",
+            "Adds two integers",
+            json!({
+                "type": "object",
+                "properties": {
+                    "a": { "type": "integer" },
+                    "b": { "type": "integer" }
+                },
+                "required": ["a", "b"]
+            }),
+            move |input: Value| {
+                calls.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    let sum = input["a"].as_i64().unwrap() + input["b"].as_i64().unwrap();
+                    Ok::<_, ToolError>(sum.to_string())
+                }
+            },
+        )
+    }
+
+    #[tokio::test]
+    async fn arguments_are_checked_against_the_schema() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let tool = add_tool(calls.clone());
+
+        let output = tool
+            .call_ext(r#"{"a": 2, "b": 3}"#, ToolCallContext::default())
+            .await
+            .unwrap();
+        assert_eq!(output.content, "5");
+
+        let error = tool
+            .call_ext(r#"{"a": 2, "b": "3"}"#, ToolCallContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ToolError::InvalidArguments(_)), "{error:?}");
+        let error = tool
+            .call_ext(r#"{"a": 2}"#, ToolCallContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ToolError::InvalidArguments(_)), "{error:?}");
+        // The plain langchain entry point validates too.
+        assert!(tool.run(json!({ "a": "2", "b": 3 })).await.is_err());
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn closure_errors_are_passed_through() {
+        let tool = FnTool::new(
+            "fail",
+            "Always fails",
+            json!({ "type": "object" }),
+            |_| async {
+                Err::<String, _>(ToolError::Tool(ToolOutput::from(
+                    "no such user".to_string(),
+                )))
+            },
+        );
+
+        let error = tool
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap_err();
+        match error {
+            ToolError::Tool(output) => assert_eq!(output.content, "no such user"),
+            other => panic!("expected a tool error, got {other:?}"),
+        }
+        assert!(tool.run(json!({})).await.is_err());
+    }
+
+    struct Failing;
+
+    #[async_trait]
+    impl Tool for Failing {
+        fn name(&self) -> String {
+            "failing".to_string()
+        }
+
+        fn description(&self) -> String {
+            "Always fails".to_string()
+        }
+
+        async fn run(&self, _input: Value) -> Result<String, Box<dyn std::error::Error>> {
+            Err("disk full".into())
+        }
+    }
+
+    #[tokio::test]
+    async fn langchain_tool_errors_become_tool_errors() {
+        let tool = LangchainTool::new(Arc::new(Failing));
+        match tool.call_ext("{}", ToolCallContext::default()).await {
+            Err(ToolError::Tool(output)) => assert_eq!(output.content, "disk full"),
+            other => panic!("expected a tool error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn native_tools_without_annotations_are_destructive() {
+        let non_destructive = ToolFilter {
+            require: vec![ToolMatcher::NonDestructive],
+            ..Default::default()
+        };
+        let policy = ApprovalPolicy::Destructive;
+
+        let bare = add_tool(Arc::default());
+        assert!(non_destructive.check("add", &describe(&bare)).is_some());
+        assert!(policy.requires_approval("add", bare.annotations().as_ref()));
+        let langchain = LangchainTool::new(Arc::new(Failing));
+        assert!(
+            non_destructive
+                .check("failing", &describe(&langchain))
+                .is_some()
+        );
+        assert!(policy.requires_approval("failing", langchain.annotations().as_ref()));
+
+        let read_only =
+            add_tool(Arc::default()).with_annotations(ToolAnnotations::new().read_only(true));
+        assert_eq!(non_destructive.check("add", &describe(&read_only)), None);
+        assert!(!policy.requires_approval("add", read_only.annotations().as_ref()));
+    }
+}
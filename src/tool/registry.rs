@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use langchain_rust::agent::AgentError;
+
+use crate::tool::extension::ToolExt;
+use crate::tool::filter::DroppedTool;
+
+/// Separator between a server namespace and a tool name, e.g. `github__search`.
+pub const DEFAULT_NAMESPACE_SEPARATOR: &str = "__";
+
+/// Longest function name the OpenAI API accepts.
+const MAX_NAME_LEN: usize = 64;
+
+/// Where a registered tool comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolSource {
+    Mcp {
+        /// Namespace the server's tools were registered under, if any.
+        server: Option<String>,
+        /// Name of the tool on the MCP server.
+        tool_name: String,
+    },
+    /// Synthetic tool wrapping `resources/list` or `resources/read`.
+    McpResources {
+        server: Option<String>,
+    },
+    Native,
+}
+
+impl Display for ToolSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ToolSource::Mcp {
+                server: Some(server),
+                tool_name,
+            } => write!(f, "MCP server `{server}` (tool `{tool_name}`)"),
+            ToolSource::Mcp {
+                server: None,
+                tool_name,
+            } => write!(f, "MCP tool `{tool_name}`"),
+            ToolSource::McpResources {
+                server: Some(server),
+            } => write!(f, "resources of MCP server `{server}`"),
+            ToolSource::McpResources { server: None } => write!(f, "MCP resources"),
+            ToolSource::Native => write!(f, "native tool"),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RegisteredTool {
+    /// The name the model sees and calls the tool by.
+    pub name: String,
+    pub source: ToolSource,
+    pub tool: Arc<dyn ToolExt>,
+}
+
+/// Tools available to an agent, keyed by their model-facing name.
+#[derive(Clone, Default)]
+pub struct ToolRegistry {
+    tools: Vec<RegisteredTool>,
+    index: HashMap<String, usize>,
+    dropped: Vec<DroppedTool>,
+}
+
+impl ToolRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `name` a valid function name: characters other than ASCII
+    /// letters, digits, `_` and `-` become `_`, and it is cut to 64 characters.
+    pub fn sanitize_name(name: &str) -> String {
+        name.trim()
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .take(MAX_NAME_LEN)
+            .collect()
+    }
+
+    /// The name the model sees for the MCP tool `name`, prefixed with
+    /// `namespace` if the server has one, e.g. `github__search`.
+    pub fn exposed_name(namespace: Option<&str>, separator: &str, name: &str) -> String {
+        match namespace {
+            Some(ns) => Self::sanitize_name(&format!("{ns}{separator}{name}")),
+            None => Self::sanitize_name(name),
+        }
+    }
+
+    /// Adds a tool under its sanitized name, failing if another tool is
+    /// already exposed under the same name.
+    pub fn register(
+        &mut self,
+        source: ToolSource,
+        tool: Arc<dyn ToolExt>,
+    ) -> Result<(), AgentError> {
+        let name = Self::sanitize_name(&tool.name());
+        if let Some(existing) = self.get(&name) {
+            return Err(AgentError::ToolError(format!(
+                "tool name `{name}` is exposed by both {} and {source}; register one of them under a different namespace",
+                existing.source
+            )));
+        }
+
+        self.index.insert(name.clone(), self.tools.len());
+        self.tools.push(RegisteredTool { name, source, tool });
+        Ok(())
+    }
+
+    /// Records a tool that was left out by a filter.
+    pub fn record_dropped(&mut self, dropped: DroppedTool) {
+        tracing::info!("Dropping tool `{}`: {}", dropped.name, dropped.reason);
+        self.dropped.push(dropped);
+    }
+
+    /// Tools that were available but filtered out.
+    pub fn dropped(&self) -> &[DroppedTool] {
+        &self.dropped
+    }
+
+    pub fn get(&self, name: &str) -> Option<&RegisteredTool> {
+        self.index
+            .get(&Self::sanitize_name(name))
+            .map(|&idx| &self.tools[idx])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &RegisteredTool> {
+        self.tools.iter()
+    }
+
+    pub fn tools(&self) -> Vec<Arc<dyn ToolExt>> {
+        self.tools.iter().map(|t| t.tool.clone()).collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tools.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tools.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::tool::extension::{ToolError, ToolOutput};
+    use crate::tool::native::FnTool;
+
+    fn tool(name: &str) -> Arc<dyn ToolExt> {
+        Arc::new(FnTool::new(
+            name,
+            "A test tool",
+            json!({ "type": "object" }),
+            |_| async { Ok::<_, ToolError>(ToolOutput::from("ok".to_string())) },
+        ))
+    }
+
+    fn mcp(server: Option<&str>, tool_name: &str) -> ToolSource {
+        ToolSource::Mcp {
+            server: server.map(str::to_string),
+            tool_name: tool_name.to_string(),
+        }
+    }
+
+    #[test]
+    fn namespaces_tool_names() {
+        assert_eq!(
+            ToolRegistry::exposed_name(Some("github"), DEFAULT_NAMESPACE_SEPARATOR, "search"),
+            "github__search"
+        );
+        assert_eq!(ToolRegistry::exposed_name(None, "__", "search"), "search");
+        assert_eq!(
+            ToolRegistry::exposed_name(Some("gh"), "-", "search"),
+            "gh-search"
+        );
+    }
+
+    #[test]
+    fn sanitizes_names_to_function_name_rules() {
+        assert_eq!(
+            ToolRegistry::exposed_name(Some("fs"), "__", "files/read.v2"),
+            "fs__files_read_v2"
+        );
+        assert_eq!(ToolRegistry::sanitize_name(" get weather "), "get_weather");
+        assert_eq!(ToolRegistry::sanitize_name("héllo"), "h_llo");
+        assert_eq!(ToolRegistry::sanitize_name(&"a".repeat(80)).len(), 64);
+    }
+
+    #[test]
+    fn reports_collisions_with_both_sources() {
+        let mut registry = ToolRegistry::new();
+        registry
+            .register(mcp(Some("github"), "search"), tool("search"))
+            .unwrap();
+        let error = registry
+            .register(mcp(Some("jira"), "search"), tool("search"))
+            .unwrap_err()
+            .to_string();
+
+        assert!(
+            error.contains("MCP server `github` (tool `search`)"),
+            "{error}"
+        );
+        assert!(
+            error.contains("MCP server `jira` (tool `search`)"),
+            "{error}"
+        );
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn looks_up_normalized_names() {
+        let mut registry = ToolRegistry::new();
+        registry
+            .register(ToolSource::Native, tool("get weather"))
+            .unwrap();
+
+        assert_eq!(registry.get(" get weather").unwrap().name, "get_weather");
+        assert!(registry.get("get_weather").is_some());
+    }
+
+    #[test]
+    fn sanitizes_native_names_like_mcp_names() {
+        let mut registry = ToolRegistry::new();
+        let long = format!("native_{}", "x".repeat(80));
+        registry
+            .register(ToolSource::Native, tool("files/read.v2"))
+            .unwrap();
+        registry.register(ToolSource::Native, tool(&long)).unwrap();
+
+        let entry = registry.get("files/read.v2").unwrap();
+        assert_eq!(entry.name, "files_read_v2");
+        assert_eq!(
+            entry.name,
+            ToolRegistry::exposed_name(None, DEFAULT_NAMESPACE_SEPARATOR, "files/read.v2")
+        );
+        let entry = registry.get(&long).unwrap();
+        assert_eq!(entry.name.len(), 64);
+        assert!(long.starts_with(&entry.name));
+
+        // The sanitized name collides with an MCP tool of the same name.
+        let error = registry
+            .register(mcp(None, "files_read_v2"), tool("files_read_v2"))
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("native tool"), "{error}");
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use langchain_rust::tools::Tool;
+use rmcp::model::{ReadResourceRequestParam, ToolAnnotations};
+use serde_json::{Map, Value, json};
+
+use crate::client::McpClient;
+use crate::tool::content::convert_resource_contents;
+use crate::tool::extension::{ToolCallContext, ToolError, ToolExt, ToolOutput};
+
+pub const LIST_RESOURCES_TOOL: &str = "list_resources";
+pub const READ_RESOURCE_TOOL: &str = "read_resource";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResourceToolKind {
+    /// Wraps `resources/list`.
+    List,
+    /// Wraps `resources/read`.
+    Read,
+}
+
+/// Synthetic tool giving the model access to the resources of MCP servers.
+///
+/// One tool can serve several servers: their resources are listed together,
+/// and a URI is read from the first server that can read it.
+pub struct McpResourceTool {
+    name: String,
+    kind: ResourceToolKind,
+    clients: Vec<Arc<dyn McpClient>>,
+}
+
+impl McpResourceTool {
+    pub(crate) fn new(
+        name: String,
+        kind: ResourceToolKind,
+        clients: Vec<Arc<dyn McpClient>>,
+    ) -> Self {
+        Self {
+            name,
+            kind,
+            clients,
+        }
+    }
+
+    pub fn kind(&self) -> ResourceToolKind {
+        self.kind
+    }
+
+    async fn list(&self) -> Result<ToolOutput, ToolError> {
+        let mut resources = Vec::new();
+        for client in &self.clients {
+            resources.extend(client.connected_peer().await?.list_all_resources().await?);
+        }
+        let structured = serde_json::to_value(&resources)
+            .map_err(|e| ToolError::Other(format!("Failed to serialize resources: {e}")))?;
+
+        let mut output = ToolOutput::from(structured.to_string());
+        output.structured = Some(structured);
+        Ok(output)
+    }
+
+    async fn read(&self, input: &Value) -> Result<ToolOutput, ToolError> {
+        let Some(uri) = input.get("uri").and_then(Value::as_str) else {
+            return Err(ToolError::InvalidArguments(format!(
+                "Invalid arguments for tool `{}`: `uri` is required. Call `{LIST_RESOURCES_TOOL}` to find available URIs.",
+                self.name
+            )));
+        };
+
+        let mut last_error = None;
+        for client in &self.clients {
+            match read_resource(client.as_ref(), uri).await {
+                Ok(output) => return Ok(output),
+                Err(e) => last_error = Some(e),
+            }
+        }
+        Err(last_error.unwrap_or_else(|| ToolError::Other(format!("Resource `{uri}` not found"))))
+    }
+}
+
+/// Reads `uri` from the server behind `client`.
+pub(crate) async fn read_resource(
+    client: &dyn McpClient,
+    uri: &str,
+) -> Result<ToolOutput, ToolError> {
+    let result = client
+        .connected_peer()
+        .await?
+        .read_resource(ReadResourceRequestParam {
+            uri: uri.to_string(),
+        })
+        .await?;
+
+    Ok(convert_resource_contents(result.contents))
+}
+
+#[async_trait]
+impl Tool for McpResourceTool {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> String {
+        match self.kind {
+            ResourceToolKind::List => "Lists the resources (documents, files, configuration, ...) \
+                published by the MCP servers, with their URI, name, description and MIME type."
+                .to_string(),
+            ResourceToolKind::Read => "Reads the content of a resource by its URI. Use the URIs \
+                returned by the list resources tool."
+                .to_string(),
+        }
+    }
+
+    fn parameters(&self) -> Value {
+        match self.kind {
+            ResourceToolKind::List => json!({
+                "type": "object",
+                "properties": {}
+            }),
+            ResourceToolKind::Read => json!({
+                "type": "object",
+                "properties": {
+                    "uri": {
+                        "type": "string",
+                        "description": "URI of the resource to read"
+                    }
+                },
+                "required": ["uri"]
+            }),
+        }
+    }
+
+    async fn run(&self, input: Value) -> Result<String, Box<dyn std::error::Error>> {
+        let output = match self.kind {
+            ResourceToolKind::List => self.list().await?,
+            ResourceToolKind::Read => self.read(&input).await?,
+        };
+        Ok(output.content)
+    }
+
+    async fn parse_input(&self, input: &str) -> Value {
+        match serde_json::from_str::<Map<String, Value>>(input) {
+            Ok(parsed_input) => Value::Object(parsed_input),
+            Err(_) => json!({
+                "uri": input,
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl ToolExt for McpResourceTool {
+    async fn call_ext(&self, input: &str, _ctx: ToolCallContext) -> Result<ToolOutput, ToolError> {
+        match self.kind {
+            ResourceToolKind::List => self.list().await,
+            ResourceToolKind::Read => self.read(&self.parse_input(input).await).await,
+        }
+    }
+
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        Some(ToolAnnotations {
+            read_only_hint: Some(true),
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::testing::{DroppedClient, StubServer, closed};
+    use crate::tool::extension::{ToolCallContext, ToolErrorKind};
+
+    fn server() -> StubServer {
+        StubServer {
+            resources: vec!["file:///notes.txt".to_string()],
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn reconnects_before_listing_and_reading() {
+        let client = Arc::new(DroppedClient::new(server()).await);
+        let list = McpResourceTool::new(
+            LIST_RESOURCES_TOOL.to_string(),
+            ResourceToolKind::List,
+            vec![client.clone()],
+        );
+
+        let output = list
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap();
+        assert!(output.content.contains("file:///notes.txt"));
+        assert_eq!(client.reconnects(), 1);
+
+        let output = read_resource(client.as_ref(), "file:///notes.txt")
+            .await
+            .unwrap();
+        assert_eq!(
+            output.content,
+            "[resource: file:///notes.txt]\nfile:///notes.txt"
+        );
+        // The new session is still open, so there is no second reconnect.
+        assert_eq!(client.reconnects(), 1);
+    }
+
+    #[tokio::test]
+    async fn closed_clients_that_cannot_reconnect_fail() {
+        let client = closed(server()).await;
+        let error = read_resource(&client, "file:///notes.txt")
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ToolErrorKind::ConnectionClosed);
+    }
+}
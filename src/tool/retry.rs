@@ -0,0 +1,127 @@
+use std::time::Duration;
+
+use crate::tool::extension::{ToolError, ToolErrorKind};
+
+/// Retry policy for MCP tool calls.
+///
+/// Only tools whose annotations mark them as safe to repeat
+/// (`idempotentHint` or `readOnlyHint`) are ever retried, whatever the policy
+/// says.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Total number of attempts, including the first one.
+    pub max_attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub multiplier: f64,
+    /// Randomizes each backoff by up to this fraction (`0.0..=1.0`) of its value.
+    pub jitter: f64,
+    /// Send failures and dropped connections by default. A reconnecting
+    /// client re-establishes the connection before the next attempt.
+    pub retry_on: Vec<ToolErrorKind>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            multiplier: 2.0,
+            jitter: 0.2,
+            retry_on: vec![ToolErrorKind::Transport, ToolErrorKind::ConnectionClosed],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new(max_attempts: u32) -> Self {
+        Self {
+            max_attempts,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration, multiplier: f64) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max;
+        self.multiplier = multiplier;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: f64) -> Self {
+        self.jitter = jitter.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_retry_on(mut self, kinds: Vec<ToolErrorKind>) -> Self {
+        self.retry_on = kinds;
+        self
+    }
+
+    /// Whether a call that failed with `error` on attempt number `attempt`
+    /// (starting at 1) should be tried again.
+    pub fn should_retry(&self, error: &ToolError, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.retry_on.contains(&error.kind())
+    }
+
+    /// Delay before the attempt following attempt number `attempt`.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self.multiplier.powi(attempt.saturating_sub(1) as i32);
+        let base = (self.initial_backoff.as_secs_f64() * exp).min(self.max_backoff.as_secs_f64());
+        let jitter = base * self.jitter * (rand::random::<f64>() * 2.0 - 1.0);
+        Duration::from_secs_f64((base + jitter).max(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rmcp::ServiceError;
+
+    use super::*;
+
+    #[test]
+    fn retries_dropped_connections_by_default() {
+        let policy = RetryPolicy::default();
+        let closed = ToolError::Service(ServiceError::TransportClosed);
+
+        assert!(policy.should_retry(&closed, 1));
+        assert!(policy.should_retry(&closed, 2));
+        assert!(!policy.should_retry(&closed, 3));
+        assert!(!policy.should_retry(&ToolError::Timeout("slow".to_string()), 1));
+        assert!(!policy.should_retry(&ToolError::Other("failed".to_string()), 1));
+    }
+
+    #[test]
+    fn retries_only_the_configured_kinds() {
+        let policy = RetryPolicy::new(5).with_retry_on(vec![ToolErrorKind::Timeout]);
+
+        assert!(policy.should_retry(&ToolError::Timeout("slow".to_string()), 4));
+        assert!(!policy.should_retry(&ToolError::Timeout("slow".to_string()), 5));
+        assert!(!policy.should_retry(&ToolError::Service(ServiceError::TransportClosed), 1));
+    }
+
+    #[test]
+    fn backoff_grows_up_to_the_maximum() {
+        let policy = RetryPolicy::default()
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(500), 2.0)
+            .with_jitter(0.0);
+
+        let delays = (1..=5)
+            .map(|attempt| (policy.backoff(attempt).as_secs_f64() * 1000.0).round())
+            .collect::<Vec<_>>();
+        assert_eq!(delays, [100.0, 200.0, 400.0, 500.0, 500.0]);
+    }
+
+    #[test]
+    fn backoff_jitter_stays_in_bounds() {
+        let policy = RetryPolicy::default()
+            .with_backoff(Duration::from_millis(100), Duration::from_secs(1), 2.0)
+            .with_jitter(0.5);
+
+        for _ in 0..100 {
+            let delay = policy.backoff(1);
+            assert!(delay >= Duration::from_millis(50) && delay <= Duration::from_millis(150));
+        }
+    }
+}
@@ -1,30 +1,246 @@
+use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
+use futures_util::StreamExt;
 use langchain_rust::tools::Tool;
-use rmcp::RoleClient;
-use rmcp::model::{CallToolRequestParam, InitializeRequestParam, object};
-use rmcp::service::RunningService;
+use rmcp::ServiceError;
+use rmcp::model::{
+    CallToolRequestParam, CallToolResult, ClientRequest, Meta, NumberOrString, ProgressToken,
+    Request, ServerResult, ToolAnnotations, object,
+};
+use rmcp::service::PeerRequestOptions;
 use serde_json::{Map, Value};
+use uuid::Uuid;
+
+use crate::client::McpClient;
+use crate::tool::content::convert_contents;
+use crate::tool::extension::{ToolCallContext, ToolError, ToolExt, ToolOutput};
+use crate::tool::retry::RetryPolicy;
+use crate::tool::schema::{SchemaValidator, coerce_arguments};
+
+/// Settings shared by every [`RmcpTool`] created by a builder.
+#[derive(Debug, Clone, Default)]
+pub struct RmcpToolOptions {
+    pub coerce_arguments: bool,
+    /// Default deadline for a single tool call, `None` waits forever.
+    pub timeout: Option<Duration>,
+    /// Per-tool overrides of `timeout`, keyed by model-facing name (e.g.
+    /// `github__search`) or by MCP tool name.
+    pub tool_timeouts: HashMap<String, Duration>,
+    pub retry: Option<RetryPolicy>,
+}
+
+impl RmcpToolOptions {
+    fn timeout_for(&self, name: &str, mcp_name: &str) -> Option<Duration> {
+        self.tool_timeouts
+            .get(name)
+            .or_else(|| self.tool_timeouts.get(mcp_name))
+            .copied()
+            .or(self.timeout)
+    }
+
+    /// Retries are only enabled for tools that declare they can safely be
+    /// repeated.
+    fn retry_for(&self, tool: &rmcp::model::Tool) -> Option<RetryPolicy> {
+        let repeatable = tool.annotations.as_ref().is_some_and(|a| {
+            a.idempotent_hint.unwrap_or(false) || a.read_only_hint.unwrap_or(false)
+        });
+        if !repeatable {
+            return None;
+        }
+        self.retry.clone()
+    }
+}
 
 pub struct RmcpTool {
+    /// Model-facing name, which may carry a server namespace.
+    name: String,
     tool: rmcp::model::Tool,
-    client: Arc<RunningService<RoleClient, InitializeRequestParam>>,
+    client: Arc<dyn McpClient>,
+    input_validator: Option<SchemaValidator>,
+    output_validator: Option<SchemaValidator>,
+    coerce_arguments: bool,
+    timeout: Option<Duration>,
+    retry: Option<RetryPolicy>,
 }
 
 impl RmcpTool {
     pub(crate) fn new(
+        name: String,
         tool: rmcp::model::Tool,
-        client: Arc<RunningService<RoleClient, InitializeRequestParam>>,
+        client: Arc<dyn McpClient>,
+        options: &RmcpToolOptions,
     ) -> Self {
-        Self { tool, client }
+        let input_validator = SchemaValidator::compile(&tool.input_schema);
+        let timeout = options.timeout_for(&name, &tool.name);
+        let retry = options.retry_for(&tool);
+        let output_validator = tool
+            .output_schema
+            .as_ref()
+            .and_then(|schema| SchemaValidator::compile(schema));
+
+        Self {
+            name,
+            tool,
+            client,
+            input_validator,
+            output_validator,
+            coerce_arguments: options.coerce_arguments,
+            timeout,
+            retry,
+        }
+    }
+
+    /// Name of the tool on the MCP server.
+    pub fn mcp_name(&self) -> &str {
+        &self.tool.name
+    }
+
+    fn prepare_input(&self, input: &mut Value) -> Result<(), ToolError> {
+        if self.coerce_arguments {
+            coerce_arguments(&self.name, &self.tool.schema_as_json_value(), input);
+        }
+        self.validate_input(input)
+    }
+
+    /// Sends `tools/call`. When a timeout is configured and expires, rmcp sends
+    /// `notifications/cancelled` for the request before returning. A closed
+    /// connection is re-established first if the client supports it.
+    ///
+    /// Progress notifications for the call are forwarded to `ctx` while waiting
+    /// for the response.
+    async fn send_call_tool(
+        &self,
+        input: Value,
+        ctx: &ToolCallContext,
+    ) -> Result<CallToolResult, ToolError> {
+        let request = ClientRequest::CallToolRequest(Request::new(CallToolRequestParam {
+            name: self.tool.name.clone(),
+            arguments: Some(object(input)),
+        }));
+
+        // Subscribe before sending so that early notifications are not lost.
+        let progress_token =
+            ProgressToken(NumberOrString::String(Uuid::now_v7().to_string().into()));
+        let mut progress = match (&ctx.progress, self.client.progress_dispatcher()) {
+            (Some(_), Some(dispatcher)) => Some(dispatcher.subscribe(progress_token.clone()).await),
+            _ => None,
+        };
+        let _tracked = ctx
+            .sampling
+            .as_ref()
+            .map(|scope| scope.track(progress_token.clone()));
+        let mut meta = Meta::new();
+        meta.set_progress_token(progress_token);
+
+        let options = PeerRequestOptions {
+            timeout: self.timeout,
+            meta: Some(meta),
+        };
+
+        let handle = self
+            .client
+            .connected_peer()
+            .await?
+            .send_cancellable_request(request, options)
+            .await?;
+
+        let response = handle.await_response();
+        tokio::pin!(response);
+        let response = loop {
+            tokio::select! {
+                response = &mut response => break response,
+                Some(p) = async { progress.as_mut()?.next().await } => {
+                    ctx.report_progress(p.progress, p.total, p.message);
+                }
+            }
+        };
+
+        match response {
+            Ok(ServerResult::CallToolResult(result)) => Ok(result),
+            Ok(_) => Err(ServiceError::UnexpectedResponse.into()),
+            Err(ServiceError::Timeout { timeout }) => Err(ToolError::Timeout(format!(
+                "Tool `{}` did not respond within {timeout:?} and the call was cancelled. It may be overloaded; try again later or continue without it.",
+                self.name
+            ))),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn validate_input(&self, input: &Value) -> Result<(), ToolError> {
+        match &self.input_validator {
+            Some(validator) => validator.validate_arguments(&self.name, input),
+            None => Ok(()),
+        }
+    }
+
+    async fn call_mcp(&self, input: Value, ctx: &ToolCallContext) -> Result<ToolOutput, ToolError> {
+        let Some(retry) = &self.retry else {
+            return self.call_mcp_once(input, ctx).await;
+        };
+
+        let mut attempt = 1;
+        loop {
+            match self.call_mcp_once(input.clone(), ctx).await {
+                Err(e) if retry.should_retry(&e, attempt) => {
+                    let backoff = retry.backoff(attempt);
+                    tracing::warn!(
+                        "call to `{}` failed (attempt {attempt}/{}), retrying in {backoff:?}: {e}",
+                        self.name,
+                        retry.max_attempts
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+
+    async fn call_mcp_once(
+        &self,
+        input: Value,
+        ctx: &ToolCallContext,
+    ) -> Result<ToolOutput, ToolError> {
+        let response = self.send_call_tool(input, ctx).await?;
+
+        let mut output = convert_contents(response.content);
+        if response.is_error.unwrap_or(false) {
+            return Err(ToolError::Tool(output));
+        }
+
+        match response.structured_content {
+            Some(structured) => {
+                if let Some(validator) = &self.output_validator {
+                    validator.validate(&structured).map_err(|e| {
+                        ToolError::InvalidOutput(format!(
+                            "structured content of `{}` does not match its output schema: {e}",
+                            self.name
+                        ))
+                    })?;
+                }
+                output.content = structured.to_string();
+                output.structured = Some(structured);
+            }
+            None if self.output_validator.is_some() => {
+                tracing::warn!(
+                    "tool `{}` declares an output schema but returned no structured content",
+                    self.name
+                );
+            }
+            None => {}
+        }
+
+        Ok(output)
     }
 }
 
 #[async_trait]
 impl Tool for RmcpTool {
     fn name(&self) -> String {
-        self.tool.name.to_string()
+        self.name.clone()
     }
 
     fn description(&self) -> String {
@@ -39,24 +255,12 @@ impl Tool for RmcpTool {
         self.tool.schema_as_json_value()
     }
 
-    async fn run(&self, input: Value) -> Result<String, Box<dyn std::error::Error>> {
-        let response = self
-            .client
-            .call_tool(CallToolRequestParam {
-                name: self.tool.name.clone(),
-                arguments: Some(object(input)),
-            })
-            .await?;
-
-        let mut resp = String::default();
-        let raw_content = response.content.unwrap_or_default();
-        for content in raw_content {
-            let t = content.as_text();
-            if let Some(text) = t {
-                resp.push_str(&text.text);
-            }
-        }
-        Ok(resp)
+    async fn run(&self, mut input: Value) -> Result<String, Box<dyn std::error::Error>> {
+        self.prepare_input(&mut input)?;
+        Ok(self
+            .call_mcp(input, &ToolCallContext::default())
+            .await?
+            .content)
     }
 
     async fn parse_input(&self, input: &str) -> Value {
@@ -68,3 +272,272 @@ impl Tool for RmcpTool {
         }
     }
 }
+
+#[async_trait]
+impl ToolExt for RmcpTool {
+    async fn call_ext(&self, input: &str, ctx: ToolCallContext) -> Result<ToolOutput, ToolError> {
+        let mut input = self.parse_input(input).await;
+        self.prepare_input(&mut input)?;
+        self.call_mcp(input, &ctx).await
+    }
+
+    fn annotations(&self) -> Option<ToolAnnotations> {
+        self.tool.annotations.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::client::testing::{StubReply, StubServer, connect, tool, tool_with_schema};
+    use crate::tool::extension::ToolErrorKind;
+
+    /// An [`RmcpTool`] calling `tool` on a new session of `server`.
+    async fn rmcp_tool(
+        server: StubServer,
+        tool: rmcp::model::Tool,
+        options: &RmcpToolOptions,
+    ) -> RmcpTool {
+        let (client, server) = connect(server).await;
+        tokio::spawn(server.waiting());
+        RmcpTool::new(tool.name.to_string(), tool, Arc::new(client), options)
+    }
+
+    fn weather_tool() -> rmcp::model::Tool {
+        let mut tool = tool("weather");
+        tool.output_schema = Some(Arc::new(object(json!({
+            "type": "object",
+            "properties": { "celsius": { "type": "number" } },
+            "required": ["celsius"]
+        }))));
+        tool
+    }
+
+    fn weather_reply(structured: Value) -> StubReply {
+        StubReply::Structured {
+            text: "It is warm".to_string(),
+            structured,
+        }
+    }
+
+    #[tokio::test]
+    async fn structured_content_replaces_the_text() {
+        let reply = weather_reply(json!({ "celsius": 21.5 }));
+        let server = StubServer::default().with_tool(weather_tool(), reply);
+        let tool = rmcp_tool(server, weather_tool(), &RmcpToolOptions::default()).await;
+
+        let output = tool
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap();
+        assert_eq!(output.structured, Some(json!({ "celsius": 21.5 })));
+        assert_eq!(output.content, r#"{"celsius":21.5}"#);
+
+        // Without an output schema, structured content is used as is.
+        let unchecked = tool_with_schema("unchecked", json!({ "type": "object" }));
+        let server = StubServer::default().with_tool(unchecked.clone(), weather_reply(json!([1])));
+        let tool = rmcp_tool(server, unchecked, &RmcpToolOptions::default()).await;
+        let output = tool
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap();
+        assert_eq!(output.structured, Some(json!([1])));
+    }
+
+    #[tokio::test]
+    async fn structured_content_must_match_the_output_schema() {
+        let reply = weather_reply(json!({ "celsius": "warm" }));
+        let server = StubServer::default().with_tool(weather_tool(), reply);
+        let tool = rmcp_tool(server, weather_tool(), &RmcpToolOptions::default()).await;
+
+        let error = tool
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap_err();
+        assert!(matches!(error, ToolError::InvalidOutput(_)), "{error:?}");
+    }
+
+    #[tokio::test]
+    async fn text_is_kept_without_structured_content() {
+        let server = StubServer::with_tools(&["echo"]);
+        let log = server.log.clone();
+        let tool = rmcp_tool(server, tool("echo"), &RmcpToolOptions::default()).await;
+
+        let output = tool
+            .call_ext(r#"{"text": "hi"}"#, ToolCallContext::default())
+            .await
+            .unwrap();
+        assert_eq!(output.content, "echo");
+        assert_eq!(output.structured, None);
+
+        let calls = log.calls();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(calls[0].name, "echo");
+        assert_eq!(calls[0].arguments, Some(object(json!({ "text": "hi" }))));
+    }
+
+    fn add_tool() -> rmcp::model::Tool {
+        tool_with_schema(
+            "add",
+            json!({
+                "type": "object",
+                "properties": {
+                    "a": { "type": "integer" },
+                    "b": { "type": "integer" }
+                },
+                "required": ["a", "b"]
+            }),
+        )
+    }
+
+    #[tokio::test]
+    async fn invalid_arguments_are_not_sent() {
+        let server = StubServer::default().with_tool(add_tool(), StubReply::Echo);
+        let log = server.log.clone();
+        let tool = rmcp_tool(server, add_tool(), &RmcpToolOptions::default()).await;
+
+        for input in [
+            r#"{"a": 1}"#,
+            r#"{"a": 1, "b": "two"}"#,
+            r#"{"a": 1.5, "b": 2}"#,
+        ] {
+            let error = tool
+                .call_ext(input, ToolCallContext::default())
+                .await
+                .unwrap_err();
+            assert!(matches!(error, ToolError::InvalidArguments(_)), "{error:?}");
+        }
+        assert!(tool.run(json!({ "a": "1", "b": 2 })).await.is_err());
+        assert!(log.calls().is_empty());
+
+        tool.call_ext(r#"{"a": 1, "b": 2}"#, ToolCallContext::default())
+            .await
+            .unwrap();
+        assert_eq!(log.calls().len(), 1);
+    }
+
+    #[test]
+    fn per_tool_timeouts_override_the_default() {
+        let options = RmcpToolOptions {
+            timeout: Some(Duration::from_secs(30)),
+            tool_timeouts: HashMap::from([
+                ("github__search".to_string(), Duration::from_secs(1)),
+                ("search".to_string(), Duration::from_secs(2)),
+                ("list".to_string(), Duration::from_secs(3)),
+            ]),
+            ..Default::default()
+        };
+
+        // The exposed name wins over the MCP name.
+        let timeout = |name, mcp_name| options.timeout_for(name, mcp_name).unwrap().as_secs();
+        assert_eq!(timeout("github__search", "search"), 1);
+        assert_eq!(timeout("gitlab__search", "search"), 2);
+        assert_eq!(timeout("github__list", "list"), 3);
+        assert_eq!(timeout("github__read", "read"), 30);
+        assert_eq!(
+            RmcpToolOptions::default().timeout_for("search", "search"),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn timed_out_calls_are_cancelled() {
+        let server = StubServer::default().with_tool(tool("slow"), StubReply::Hang);
+        let log = server.log.clone();
+        let options = RmcpToolOptions {
+            timeout: Some(Duration::from_secs(60)),
+            tool_timeouts: HashMap::from([("slow".to_string(), Duration::from_millis(100))]),
+            ..Default::default()
+        };
+        let tool = rmcp_tool(server, tool("slow"), &options).await;
+
+        let started = std::time::Instant::now();
+        let error = tool
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap_err();
+        assert!(started.elapsed() < Duration::from_secs(10));
+        assert!(matches!(error, ToolError::Timeout(_)), "{error:?}");
+        assert_eq!(error.kind(), ToolErrorKind::Timeout);
+
+        // The server is told to stop working on the call.
+        log.cancelled(1).await;
+    }
+
+    fn retry_options() -> RmcpToolOptions {
+        RmcpToolOptions {
+            retry: Some(
+                RetryPolicy::new(3)
+                    .with_retry_on(vec![ToolErrorKind::Protocol])
+                    .with_backoff(Duration::from_millis(1), Duration::from_millis(1), 1.0),
+            ),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn tools_without_hints_are_not_retried() {
+        let server = StubServer::default().with_tool(tool("send"), StubReply::Fail);
+        let log = server.log.clone();
+        let tool = rmcp_tool(server, tool("send"), &retry_options()).await;
+
+        let error = tool
+            .call_ext("{}", ToolCallContext::default())
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), ToolErrorKind::Protocol);
+        assert_eq!(log.calls().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn repeatable_tools_are_retried_up_to_max_attempts() {
+        for annotations in [
+            ToolAnnotations::new().idempotent(true),
+            ToolAnnotations::new().read_only(true),
+        ] {
+            let lookup = tool("lookup").annotate(annotations);
+            let server = StubServer::default().with_tool(lookup.clone(), StubReply::Fail);
+            let log = server.log.clone();
+            let tool = rmcp_tool(server, lookup, &retry_options()).await;
+
+            let error = tool
+                .call_ext("{}", ToolCallContext::default())
+                .await
+                .unwrap_err();
+            assert_eq!(error.kind(), ToolErrorKind::Protocol);
+            assert_eq!(log.calls().len(), 3);
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_is_reported_for_the_tool_call() {
+        let server =
+            StubServer::default().with_tool(tool("index"), StubReply::Progress { steps: 2 });
+        let log = server.log.clone();
+        let tool = rmcp_tool(server, tool("index"), &RmcpToolOptions::default()).await;
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let ctx = ToolCallContext::new("call_1").with_progress(tx);
+        let output = tool.call_ext("{}", ctx).await.unwrap();
+        assert_eq!(output.content, "index");
+        assert!(log.calls()[0].progress_token.is_some());
+
+        let mut received = Vec::new();
+        while let Ok(progress) = rx.try_recv() {
+            received.push(progress);
+        }
+        assert_eq!(received.len(), 2, "{received:?}");
+        for (step, progress) in received.iter().enumerate() {
+            let step = step + 1;
+            assert_eq!(progress.tool_call_id, "call_1");
+            assert_eq!(progress.progress, step as f64);
+            assert_eq!(progress.total, Some(2.0));
+            assert_eq!(
+                progress.message.as_deref(),
+                Some(format!("step {step}").as_str())
+            );
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use std::sync::Arc;
+
+use jsonschema::Validator;
+use rmcp::model::JsonObject;
+use serde_json::Value;
+
+use crate::tool::extension::ToolError;
+
+/// A compiled JSON schema, shared between clones of a tool.
+#[derive(Clone)]
+pub struct SchemaValidator {
+    validator: Arc<Validator>,
+}
+
+impl SchemaValidator {
+    /// Compiles `schema`, returning `None` (and logging why) if it is not a
+    /// usable JSON schema. Tools with broken schemas are still callable, they
+    /// just don't get validated.
+    pub fn compile(schema: &JsonObject) -> Option<Self> {
+        match jsonschema::validator_for(&Value::Object(schema.clone())) {
+            Ok(validator) => Some(Self {
+                validator: Arc::new(validator),
+            }),
+            Err(e) => {
+                tracing::warn!("ignoring invalid JSON schema: {e}");
+                None
+            }
+        }
+    }
+
+    /// Validates `instance`, collecting every violation into one message of
+    /// the form `` `/path`: reason; `/other`: reason ``.
+    pub fn validate(&self, instance: &Value) -> Result<(), String> {
+        let errors = self
+            .validator
+            .iter_errors(instance)
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                let path = if path.is_empty() {
+                    "/".to_string()
+                } else {
+                    path
+                };
+                format!("`{path}`: {e}")
+            })
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors.join("; "))
+        }
+    }
+
+    /// Validates the arguments of a call to `tool_name`. The error asks the
+    /// model to fix them, as it is handed back as the observation.
+    pub fn validate_arguments(&self, tool_name: &str, input: &Value) -> Result<(), ToolError> {
+        self.validate(input).map_err(|e| {
+            ToolError::InvalidArguments(format!(
+                "Invalid arguments for tool `{tool_name}`: {e}. Fix the arguments to match the tool's parameter schema and call it again."
+            ))
+        })
+    }
+}
+
+/// Maximum number of `$ref` hops followed while coercing, guards against
+/// recursive schemas.
+const MAX_REF_DEPTH: usize = 32;
+
+/// Best-effort fix-up of model-produced arguments using the tool's JSON
+/// schema, applied before validation:
+///
+/// - integral floats (`7.0`) where an integer is expected
+/// - numeric / boolean strings (`"7"`, `"true"`) where a number / boolean is expected
+/// - single values where an array is expected
+/// - enum strings that only differ in case
+///
+/// Every coercion applied is logged.
+pub fn coerce_arguments(tool_name: &str, schema: &Value, args: &mut Value) {
+    let coercer = Coercer {
+        tool_name,
+        root: schema,
+    };
+    coercer.coerce(schema, args, "", 0);
+}
+
+struct Coercer<'a> {
+    tool_name: &'a str,
+    root: &'a Value,
+}
+
+impl Coercer<'_> {
+    fn coerce(&self, schema: &Value, value: &mut Value, path: &str, depth: usize) {
+        let Some(schema) = self.resolve(schema, depth) else {
+            return;
+        };
+
+        if let Some(all_of) = schema.get("allOf").and_then(|s| s.as_array()) {
+            for sub in all_of {
+                self.coerce(sub, value, path, depth + 1);
+            }
+        }
+
+        // Unions: follow the branch the value already matches, so that only
+        // nested fields are coerced, or else the first non-null branch, as
+        // for `Option<T>`.
+        if !value.is_null()
+            && let Some(branches) = schema
+                .get("anyOf")
+                .or_else(|| schema.get("oneOf"))
+                .and_then(|s| s.as_array())
+        {
+            let matching = branches.iter().find(|branch| {
+                self.resolve(branch, depth + 1)
+                    .is_some_and(|branch| matches_type(&schema_types(branch), value))
+            });
+            let branch = matching.or_else(|| {
+                branches
+                    .iter()
+                    .find(|s| s.get("type").and_then(|t| t.as_str()) != Some("null"))
+            });
+            if let Some(branch) = branch {
+                self.coerce(branch, value, path, depth + 1);
+            }
+        }
+
+        let types = schema_types(schema);
+        if !types.is_empty() && !matches_type(&types, value) {
+            self.coerce_type(&types, value, path);
+        }
+
+        if let Some(options) = schema.get("enum").and_then(|e| e.as_array()) {
+            self.coerce_enum(options, value, path);
+        }
+
+        match value {
+            Value::Object(map) => {
+                let properties = schema.get("properties").and_then(|p| p.as_object());
+                let additional = schema.get("additionalProperties").filter(|a| a.is_object());
+                for (key, field) in map.iter_mut() {
+                    let sub_schema = properties.and_then(|p| p.get(key)).or(additional);
+                    if let Some(sub_schema) = sub_schema {
+                        self.coerce(sub_schema, field, &format!("{path}/{key}"), depth + 1);
+                    }
+                }
+            }
+            Value::Array(items) => {
+                let prefix = schema
+                    .get("prefixItems")
+                    .or_else(|| schema.get("items").filter(|i| i.is_array()))
+                    .and_then(|p| p.as_array());
+                let rest = schema.get("items").filter(|i| i.is_object());
+                for (idx, item) in items.iter_mut().enumerate() {
+                    let sub_schema = prefix.and_then(|p| p.get(idx)).or(rest);
+                    if let Some(sub_schema) = sub_schema {
+                        self.coerce(sub_schema, item, &format!("{path}/{idx}"), depth + 1);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn resolve<'s>(&'s self, schema: &'s Value, depth: usize) -> Option<&'s Value> {
+        if depth > MAX_REF_DEPTH {
+            return None;
+        }
+        match schema.get("$ref").and_then(|r| r.as_str()) {
+            Some(reference) => {
+                let pointer = reference.strip_prefix('#')?;
+                let target = self.root.pointer(pointer)?;
+                self.resolve(target, depth + 1)
+            }
+            None => Some(schema),
+        }
+    }
+
+    fn coerce_type(&self, types: &[&str], value: &mut Value, path: &str) {
+        let coerced = match &*value {
+            Value::Number(n) if types.contains(&"integer") => n
+                .as_f64()
+                .filter(|f| f.fract() == 0.0 && f.abs() < i64::MAX as f64)
+                .map(|f| Value::from(f as i64)),
+            Value::String(s) if types.contains(&"integer") => {
+                let s = s.trim();
+                s.parse::<i64>().ok().map(Value::from).or_else(|| {
+                    s.parse::<f64>()
+                        .ok()
+                        .filter(|f| f.fract() == 0.0 && f.abs() < i64::MAX as f64)
+                        .map(|f| Value::from(f as i64))
+                })
+            }
+            Value::String(s) if types.contains(&"number") => s
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .and_then(|f| serde_json::Number::from_f64(f).map(Value::Number)),
+            Value::String(s) if types.contains(&"boolean") => {
+                match s.trim().to_ascii_lowercase().as_str() {
+                    "true" => Some(Value::Bool(true)),
+                    "false" => Some(Value::Bool(false)),
+                    _ => None,
+                }
+            }
+            v if types.contains(&"array") && !v.is_null() => Some(Value::Array(vec![v.clone()])),
+            _ => None,
+        };
+
+        if let Some(coerced) = coerced {
+            self.log(path, value, &coerced);
+            *value = coerced;
+        }
+    }
+
+    fn coerce_enum(&self, options: &[Value], value: &mut Value, path: &str) {
+        let Value::String(s) = &*value else {
+            return;
+        };
+        if options.contains(value) {
+            return;
+        }
+
+        let matched = options.iter().find(|option| {
+            option
+                .as_str()
+                .is_some_and(|option| option.eq_ignore_ascii_case(s))
+        });
+        if let Some(matched) = matched.cloned() {
+            self.log(path, value, &matched);
+            *value = matched;
+        }
+    }
+
+    fn log(&self, path: &str, before: &Value, after: &Value) {
+        let path = if path.is_empty() { "/" } else { path };
+        tracing::info!(
+            "coerced argument `{path}` of tool `{}`: {before} -> {after}",
+            self.tool_name
+        );
+    }
+}
+
+fn schema_types(schema: &Value) -> Vec<&str> {
+    match schema.get("type") {
+        Some(Value::String(t)) => vec![t.as_str()],
+        Some(Value::Array(types)) => types.iter().filter_map(|t| t.as_str()).collect(),
+        _ => vec![],
+    }
+}
+
+/// Whether `value` is one of `types`, integers counting as numbers.
+fn matches_type(types: &[&str], value: &Value) -> bool {
+    let value_type = value_type(value);
+    types.contains(&value_type) || (value_type == "integer" && types.contains(&"number"))
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_i64() || n.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    fn coerce(schema: Value, mut args: Value) -> Value {
+        coerce_arguments("test", &schema, &mut args);
+        args
+    }
+
+    #[test]
+    fn coerces_integers() {
+        let schema = json!({ "type": "integer" });
+        assert_eq!(coerce(schema.clone(), json!(7.0)), json!(7));
+        assert_eq!(coerce(schema.clone(), json!("7")), json!(7));
+        assert_eq!(coerce(schema.clone(), json!(7.5)), json!(7.5));
+        assert_eq!(coerce(schema, json!("seven")), json!("seven"));
+    }
+
+    #[test]
+    fn coerces_numbers() {
+        let schema = json!({ "type": "number" });
+        assert_eq!(coerce(schema.clone(), json!("2.5")), json!(2.5));
+        assert_eq!(coerce(schema, json!(3)), json!(3));
+    }
+
+    #[test]
+    fn coerces_booleans() {
+        let schema = json!({ "type": "boolean" });
+        assert_eq!(coerce(schema.clone(), json!("true")), json!(true));
+        assert_eq!(coerce(schema.clone(), json!(" FALSE ")), json!(false));
+        assert_eq!(coerce(schema, json!("yes")), json!("yes"));
+    }
+
+    #[test]
+    fn coerces_nested_objects() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "page": {
+                    "type": "object",
+                    "properties": {
+                        "size": { "type": "integer" },
+                        "order": { "type": "string", "enum": ["asc", "desc"] }
+                    }
+                },
+                "tags": { "type": "array", "items": { "type": "string" } }
+            }
+        });
+        let args = json!({
+            "page": { "size": "20", "order": "DESC" },
+            "tags": "rust"
+        });
+        assert_eq!(
+            coerce(schema, args),
+            json!({
+                "page": { "size": 20, "order": "desc" },
+                "tags": ["rust"]
+            })
+        );
+    }
+
+    #[test]
+    fn any_of_keeps_values_matching_a_branch() {
+        let schema = json!({ "anyOf": [{ "type": "integer" }, { "type": "string" }] });
+        assert_eq!(coerce(schema.clone(), json!("7")), json!("7"));
+        assert_eq!(coerce(schema, json!(7.0)), json!(7));
+
+        let optional = json!({ "anyOf": [{ "type": "integer" }, { "type": "null" }] });
+        assert_eq!(coerce(optional.clone(), json!("7")), json!(7));
+        assert_eq!(coerce(optional, Value::Null), Value::Null);
+
+        let nested = json!({
+            "oneOf": [
+                { "type": "string" },
+                { "type": "object", "properties": { "limit": { "type": "integer" } } }
+            ]
+        });
+        assert_eq!(
+            coerce(nested, json!({ "limit": "5" })),
+            json!({ "limit": 5 })
+        );
+    }
+}